@@ -0,0 +1,367 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+
+use crate::condition::{self, Condition};
+use crate::config::AlertRule;
+use crate::energy::format_elapsed;
+use crate::sample::{self, Sample};
+
+#[derive(Clone, Copy, PartialEq, PartialOrd, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        })
+    }
+}
+
+// A condition that has to hold `for` a while, optionally on the average `avg` over a window,
+// e.g. `p_cpu_active > 90 for 30s` or `package_w > 20 avg 10s`
+#[derive(Clone, PartialEq)]
+pub struct Rule {
+    pub condition: Condition,
+    pub hold: Duration,
+    pub average: Option<Duration>,
+    text: String,
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let split = words
+            .iter()
+            .position(|word| *word == "for" || *word == "avg")
+            .unwrap_or(words.len());
+        let mut rule = Self {
+            condition: words[..split].join(" ").parse()?,
+            hold: Duration::ZERO,
+            average: None,
+            text: words.join(" "),
+        };
+        for pair in words[split..].chunks(2) {
+            let duration = pair
+                .get(1)
+                .ok_or_else(|| format!("expected a duration after `{}`", pair[0]))?;
+            let duration = condition::parse_duration(duration)?;
+            match pair[0] {
+                "for" => rule.hold = duration,
+                "avg" => rule.average = Some(duration),
+                other => return Err(format!("expected `for` or `avg`, not `{}`", other)),
+            }
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+// An alert starting to fire or resolving
+pub struct Event {
+    pub rule: AlertRule,
+    pub firing: bool,
+    pub value: f64,
+    pub timestamp: SystemTime,
+    // When the condition started holding
+    pub since: SystemTime,
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {} ({})",
+            if self.firing { "FIRING" } else { "RESOLVED" },
+            self.rule.severity,
+            self.rule.rule,
+            format_value(self.rule.rule.condition.field(), self.value)
+        )?;
+        if !self.firing {
+            let held = self
+                .timestamp
+                .duration_since(self.since)
+                .unwrap_or_default();
+            write!(f, " after {}", format_elapsed(held))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct State {
+    // (timestamp, value, elapsed ms) over the averaging window
+    window: VecDeque<(SystemTime, f64, f64)>,
+    value: f64,
+    since: Option<SystemTime>,
+    firing: bool,
+}
+
+// Evaluates every rule against each sample, tracking which are firing
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+    states: Vec<State>,
+}
+
+impl Alerts {
+    pub fn new(rules: &[AlertRule]) -> Self {
+        Self {
+            rules: rules.to_vec(),
+            states: rules.iter().map(|_| State::default()).collect(),
+        }
+    }
+
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    // Takes new rules from a config reload; unchanged ones keep their state, so a firing alert
+    // doesn't fire again and a held condition doesn't start its `for` over
+    pub fn reload(&mut self, rules: &[AlertRule]) {
+        let mut old: Vec<Option<State>> = self.states.drain(..).map(Some).collect();
+        self.states = rules
+            .iter()
+            .map(|rule| {
+                self.rules
+                    .iter()
+                    .zip(&mut old)
+                    .find(|(old_rule, state)| *old_rule == rule && state.is_some())
+                    .and_then(|(_, state)| state.take())
+                    .unwrap_or_default()
+            })
+            .collect();
+        self.rules = rules.to_vec();
+    }
+
+    pub fn update(&mut self, sample: &Sample) -> Vec<Event> {
+        let mut events = Vec::new();
+        let start = sample.start();
+        for (rule, state) in self.rules.iter().zip(&mut self.states) {
+            let condition = &rule.rule.condition;
+            state.value = match rule.rule.average {
+                Some(window) => state.average(sample, condition.value_of(sample), window),
+                None => condition.value_of(sample),
+            };
+
+            let since = if state.firing {
+                // Resolving needs the value back past the limit by the hysteresis, so it doesn't flap
+                if condition.test_with_margin(state.value, rule.hysteresis) {
+                    continue;
+                }
+                state.firing = false;
+                state.since.take()
+            } else if condition.test(state.value) {
+                let since = *state.since.get_or_insert(start);
+                if sample.timestamp.duration_since(since).unwrap_or_default() < rule.rule.hold {
+                    continue;
+                }
+                state.firing = true;
+                Some(since)
+            } else {
+                state.since = None;
+                continue;
+            };
+            events.push(Event {
+                rule: rule.clone(),
+                firing: state.firing,
+                value: state.value,
+                timestamp: sample.timestamp,
+                since: since.unwrap_or(start),
+            });
+        }
+        events
+    }
+
+    // Firing rules with their current value and when they started holding, most severe first
+    pub fn firing(&self) -> Vec<(&AlertRule, f64, SystemTime)> {
+        let mut firing: Vec<_> = self
+            .rules
+            .iter()
+            .zip(&self.states)
+            .filter(|(_, state)| state.firing)
+            .map(|(rule, state)| (rule, state.value, state.since.unwrap_or(SystemTime::now())))
+            .collect();
+        firing.sort_by(|a, b| b.0.severity.partial_cmp(&a.0.severity).unwrap());
+        firing
+    }
+
+    // Whether any rule on one of these fields is firing, for highlighting their chart
+    pub fn on(&self, fields: &[&str]) -> bool {
+        self.firing()
+            .iter()
+            .any(|(rule, ..)| fields.contains(&sample::FIELDS[rule.rule.condition.field()]))
+    }
+}
+
+impl State {
+    // Time-weighted mean over the last `window` of samples
+    fn average(&mut self, sample: &Sample, value: f64, window: Duration) -> f64 {
+        self.window
+            .push_back((sample.timestamp, value, sample.elapsed_ms.max(1.0)));
+        while let Some((timestamp, ..)) = self.window.front() {
+            if sample
+                .timestamp
+                .duration_since(*timestamp)
+                .unwrap_or_default()
+                < window
+            {
+                break;
+            }
+            self.window.pop_front();
+        }
+        let weight: f64 = self.window.iter().map(|(_, _, weight)| weight).sum();
+        self.window
+            .iter()
+            .map(|(_, value, elapsed)| value * elapsed)
+            .sum::<f64>()
+            / weight
+    }
+}
+
+// A metric's value with its unit, e.g. `2.40 GB` or `95%`
+pub fn format_value(field: usize, value: f64) -> String {
+    let name = sample::FIELDS[field];
+    if name.ends_with("_level") {
+        sample::thermal_pressure_name(value).to_string()
+    } else if name.ends_with("_percent") {
+        format!("{:.0}%", value)
+    } else if name.ends_with("_bytes") {
+        format!("{:.2} GB", value / 1024.0 / 1024.0 / 1024.0)
+    } else if name.ends_with("_w") {
+        format!("{:.2} W", value)
+    } else if name.ends_with("_mhz") {
+        format!("{:.0} MHz", value)
+    } else {
+        format!("{:.1}", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    const START: Duration = Duration::from_secs(1_000);
+
+    fn rule(text: &str) -> AlertRule {
+        AlertRule::new(text.parse().unwrap())
+    }
+
+    // A sample ending `secs` into the run after measuring `elapsed` seconds
+    fn sample(secs: u64, elapsed: u64, package_w: f64) -> Sample {
+        let mut sample = Sample::from_values(UNIX_EPOCH + START, &[0.0; sample::FIELDS.len()]);
+        sample.timestamp += Duration::from_secs(secs);
+        sample.elapsed_ms = elapsed as f64 * 1000.0;
+        sample.package_w = package_w;
+        sample
+    }
+
+    // Five seconds above 15 W, then a three-second stall at 30 W between quiet samples
+    fn run() -> Vec<Sample> {
+        let mut samples: Vec<Sample> = (1..=8)
+            .map(|secs| sample(secs, 1, if (3..=7).contains(&secs) { 20.0 } else { 5.0 }))
+            .collect();
+        samples.push(sample(11, 3, 30.0));
+        samples.push(sample(12, 1, 5.0));
+        samples.push(sample(13, 1, 5.0));
+        samples
+    }
+
+    // Whether each event fired or resolved, and when, in seconds into the run
+    fn events(rule: AlertRule) -> Vec<(bool, u64)> {
+        let mut alerts = Alerts::new(&[rule]);
+        run()
+            .iter()
+            .flat_map(|sample| alerts.update(sample))
+            .map(|event| {
+                let at = event.timestamp.duration_since(UNIX_EPOCH + START).unwrap();
+                (event.firing, at.as_secs())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fires_and_resolves_as_the_value_crosses() {
+        let expected = vec![(true, 3), (false, 8), (true, 11), (false, 12)];
+        assert_eq!(events(rule("package_w > 15")), expected);
+    }
+
+    #[test]
+    fn holds_for_the_duration_before_firing() {
+        // Held from when the first sample above the limit began, at 2s; the stall isn't long enough
+        assert_eq!(
+            events(rule("package_w > 15 for 4s")),
+            vec![(true, 6), (false, 8)]
+        );
+        let mut alerts = Alerts::new(&[rule("package_w > 15 for 4s")]);
+        let fired = run()
+            .iter()
+            .flat_map(|sample| alerts.update(sample))
+            .next()
+            .unwrap();
+        assert_eq!(fired.since, UNIX_EPOCH + START + Duration::from_secs(2));
+        assert_eq!(fired.value, 20.0);
+    }
+
+    #[test]
+    fn averages_over_the_window_by_elapsed_time() {
+        // The stall counts three times over the quiet samples after it, keeping the average up
+        assert_eq!(
+            events(rule("package_w > 15 avg 3s")),
+            vec![(true, 5), (false, 8), (true, 11)]
+        );
+    }
+
+    #[test]
+    fn hysteresis_delays_resolving() {
+        let mut sticky = rule("package_w > 15");
+        sticky.hysteresis = 12.0;
+        assert_eq!(events(sticky), vec![(true, 3)]);
+    }
+
+    #[test]
+    fn parses_rules() {
+        let parsed: Rule = "package_w > 20  avg 10s for 1m".parse().unwrap();
+        assert_eq!(parsed.average, Some(Duration::from_secs(10)));
+        assert_eq!(parsed.hold, Duration::from_secs(60));
+        assert_eq!(parsed.to_string(), "package_w > 20 avg 10s for 1m");
+        assert!("package_w > 20 for".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn reload_keeps_state_of_unchanged_rules() {
+        let held = rule("package_w > 20 for 2s");
+        let mut alerts = Alerts::new(std::slice::from_ref(&held));
+        let fired: usize = (1..=3)
+            .map(|secs| alerts.update(&sample(secs, 1, 25.0)).len())
+            .sum();
+        assert_eq!(fired, 1);
+
+        alerts.reload(&[rule("cpu_w > 5"), held.clone()]);
+        assert!(alerts.update(&sample(4, 1, 25.0)).is_empty());
+        assert_eq!(alerts.firing().len(), 1);
+
+        // A changed rule starts over
+        alerts.reload(&[rule("package_w > 20 for 3s")]);
+        assert!(alerts.firing().is_empty());
+        assert!(alerts.update(&sample(5, 1, 25.0)).is_empty());
+    }
+}
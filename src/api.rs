@@ -0,0 +1,163 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::Sender;
+use serde_json::{json, Value};
+
+use crate::condition;
+use crate::export;
+use crate::history::{History, SharedHistory};
+use crate::sample;
+use crate::summary;
+use crate::timeseries;
+use crate::AppleSiliconInfo;
+
+const USAGE: &str =
+    "commands are info, latest, history <metric> [window], summary [window] and mark <name>";
+
+// Answers one-line text commands on a Unix socket with one line of JSON each, from the same
+// history the dashboard draws; the socket goes away with the server
+pub struct ApiServer {
+    path: PathBuf,
+}
+
+impl ApiServer {
+    pub fn serve(
+        path: &Path,
+        mode: u32,
+        info: &AppleSiliconInfo,
+        history: SharedHistory,
+        markers: Sender<String>,
+    ) -> io::Result<Self> {
+        // A socket left behind by an earlier run would make bind fail
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "exists and is not a socket",
+                ));
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "another mtop is serving it",
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        // mtop runs as root, so only root can ask unless `mode` lets others in
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+
+        let info = summary::info_json(info);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let history = Arc::clone(&history);
+                let info = info.clone();
+                let markers = markers.clone();
+                // Clients may keep the connection open for many requests
+                thread::spawn(move || {
+                    let _ = handle(stream, &history, &info, &markers);
+                });
+            }
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn handle(
+    stream: UnixStream,
+    history: &SharedHistory,
+    info: &Value,
+    markers: &Sender<String>,
+) -> io::Result<()> {
+    let mut out = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = respond(line.trim(), &history.lock().unwrap(), info, markers)
+            .unwrap_or_else(|error| json!({ "error": error }));
+        writeln!(out, "{}", response)?;
+    }
+    Ok(())
+}
+
+fn respond(
+    request: &str,
+    history: &History,
+    info: &Value,
+    markers: &Sender<String>,
+) -> Result<Value, String> {
+    let (command, rest) = request.split_once(' ').unwrap_or((request, ""));
+    let args: Vec<&str> = rest.split_whitespace().collect();
+    let now = Instant::now();
+    match command {
+        "info" => Ok(info.clone()),
+        "latest" => history
+            .latest()
+            .map(export::sample_json)
+            .ok_or_else(|| "no samples yet".to_string()),
+        "history" => {
+            let metric = args.first().ok_or("usage: history <metric> [window]")?;
+            let field = condition::resolve_field(metric)?;
+            let series = history.series(field);
+            let points: Vec<Value> = series
+                .points(now, window(args.get(1))?)
+                .iter()
+                .map(|point| {
+                    json!([
+                        sample::format_timestamp(history.timestamp(point.time)),
+                        point.avg
+                    ])
+                })
+                .collect();
+            Ok(json!({
+                "metric": sample::FIELDS[field],
+                "last": series.last(),
+                "points": points
+            }))
+        }
+        "summary" => {
+            let window = window(args.first())?;
+            let summary = history
+                .summary(now, window)
+                .ok_or("no samples in the window")?;
+            let start = history.series(0).points(now, window)[0].time;
+            let mut record = summary.to_json();
+            record["start"] = json!(sample::format_timestamp(history.timestamp(start)));
+            Ok(record)
+        }
+        "mark" if !rest.trim().is_empty() => {
+            markers
+                .send(rest.trim().to_string())
+                .map_err(|_| "mtop is shutting down".to_string())?;
+            Ok(json!({ "marked": rest.trim() }))
+        }
+        _ => Err(format!("unknown request `{}`; {}", request, USAGE)),
+    }
+}
+
+// A window like `5m`, or everything kept
+fn window(text: Option<&&str>) -> Result<Duration, String> {
+    match text {
+        Some(text) => condition::parse_duration(text),
+        None => Ok(timeseries::MAX_RETENTION),
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::condition::Condition;
+use crate::export::Sink;
+use crate::history::SharedHistory;
+use crate::marker::Marker;
+use crate::recording::RecordingSink;
+use crate::sample::{self, Sample};
+use crate::AppleSiliconInfo;
+
+// Oscilloscope-style capture: when a trigger goes from false to true, writes the last `pre` of
+// samples, which the shared history keeps, and the following `post` to a new recording
+pub struct CaptureSink {
+    triggers: Vec<Condition>,
+    pre: Duration,
+    post: Duration,
+    dir: PathBuf,
+    info: AppleSiliconInfo,
+    interval_ms: u64,
+    history: SharedHistory,
+    armed: bool,
+    active: Option<Capture>,
+    count: usize,
+    status: Option<String>,
+}
+
+struct Capture {
+    sink: RecordingSink,
+    path: PathBuf,
+    until: SystemTime,
+}
+
+impl CaptureSink {
+    pub fn new(
+        triggers: &[Condition],
+        pre: Duration,
+        post: Duration,
+        dir: PathBuf,
+        info: &AppleSiliconInfo,
+        interval_ms: u64,
+        history: SharedHistory,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        history.lock().unwrap().keep_samples(pre);
+        Ok(Self {
+            triggers: triggers.to_vec(),
+            pre,
+            post,
+            dir,
+            info: info.clone(),
+            interval_ms,
+            history,
+            // A condition that already holds at startup has to clear before it can fire
+            armed: false,
+            active: None,
+            count: 0,
+            status: None,
+        })
+    }
+
+    fn start(&mut self, trigger: &Condition, sample: &Sample) -> io::Result<()> {
+        let earlier = self
+            .history
+            .lock()
+            .unwrap()
+            .samples_before(sample, self.pre);
+        let first = earlier.first().unwrap_or(sample);
+        let start = first.start();
+        let path = capture_path(&self.dir, sample.timestamp);
+        let mut sink = RecordingSink::create(&path, &self.info, self.interval_ms, start)?;
+        for earlier in &earlier {
+            sink.write(earlier)?;
+        }
+        self.count += 1;
+        self.status = Some(format!("capturing {} after `{}`", path.display(), trigger));
+        self.active = Some(Capture {
+            sink,
+            path,
+            until: sample.timestamp + self.post,
+        });
+        Ok(())
+    }
+}
+
+impl Sink for CaptureSink {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let fired = self.triggers.iter().find(|trigger| trigger.holds(sample));
+        let rising = fired.is_some() && self.armed;
+        self.armed = fired.is_none();
+        if rising && self.active.is_none() {
+            let trigger = fired.unwrap().clone();
+            self.start(&trigger, sample)?;
+        }
+
+        if let Some(capture) = &mut self.active {
+            capture.sink.write(sample)?;
+            if sample.timestamp >= capture.until {
+                self.status = Some(format!(
+                    "{} saved, last {}",
+                    self.count,
+                    capture.path.display()
+                ));
+                self.active = None;
+            }
+        }
+        Ok(())
+    }
+
+    fn mark(&mut self, marker: &Marker) -> io::Result<()> {
+        match &mut self.active {
+            Some(capture) => capture.sink.mark(marker),
+            None => Ok(()),
+        }
+    }
+
+    fn status(&self) -> Option<String> {
+        let status = self.status.as_deref().unwrap_or("waiting for a trigger");
+        Some(format!("Capture: {}", status))
+    }
+}
+
+// A file name no earlier capture in `dir` has taken, e.g. mtop-capture-20240501T120000.250Z.rec,
+// then mtop-capture-20240501T120000.250Z-2.rec for another in the same millisecond
+fn capture_path(dir: &Path, time: SystemTime) -> PathBuf {
+    let stamp = file_stamp(time);
+    (1..)
+        .map(|n| match n {
+            1 => dir.join(format!("mtop-capture-{}.rec", stamp)),
+            n => dir.join(format!("mtop-capture-{}-{}.rec", stamp, n)),
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+// Compact UTC time for file names, to the millisecond, e.g. 20240501T120000.250Z
+fn file_stamp(time: SystemTime) -> String {
+    sample::format_timestamp(time)
+        .chars()
+        .filter(|c| *c != '-' && *c != ':')
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_captures_apart() {
+        let dir = std::env::temp_dir().join(format!("mtop-{}-captures", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let time = sample::parse_timestamp("2024-05-01T12:00:00.250Z").unwrap();
+        let first = capture_path(&dir, time);
+        assert_eq!(first, dir.join("mtop-capture-20240501T120000.250Z.rec"));
+        fs::write(&first, "").unwrap();
+        let second = capture_path(&dir, time);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(second, dir.join("mtop-capture-20240501T120000.250Z-2.rec"));
+        let later = time + Duration::from_millis(1);
+        assert_eq!(
+            capture_path(&dir, later),
+            dir.join("mtop-capture-20240501T120000.251Z.rec")
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::path::Path;
+
+use crossbeam_channel::unbounded;
+
+use crate::cli::CheckArgs;
+use crate::run;
+use crate::summary::Summary;
+use crate::{recording, spawn_collector};
+
+// One limit compared against the summary
+pub struct Outcome {
+    pub label: &'static str,
+    pub value: f64,
+    pub limit: f64,
+    pub unit: &'static str,
+}
+
+impl Outcome {
+    pub fn passed(&self) -> bool {
+        self.value <= self.limit
+    }
+}
+
+// Every limit given on the command line, in a fixed order
+pub fn evaluate(args: &CheckArgs, summary: &Summary) -> Vec<Outcome> {
+    let rules = [
+        (
+            args.max_avg_package_w,
+            "avg package power",
+            summary.mean("package_w"),
+            "W",
+        ),
+        (
+            args.max_avg_cpu_w,
+            "avg CPU power",
+            summary.mean("cpu_w"),
+            "W",
+        ),
+        (
+            args.max_avg_gpu_w,
+            "avg GPU power",
+            summary.mean("gpu_w"),
+            "W",
+        ),
+        (
+            args.max_peak_package_w,
+            "peak package power",
+            summary.max("package_w"),
+            "W",
+        ),
+        (
+            args.max_energy_j,
+            "package energy",
+            summary.energy.package,
+            "J",
+        ),
+        (
+            args.max_p95_e_cpu,
+            "p95 E-CPU residency",
+            summary.p95("e_cpu_active_percent"),
+            "%",
+        ),
+        (
+            args.max_p95_p_cpu,
+            "p95 P-CPU residency",
+            summary.p95("p_cpu_active_percent"),
+            "%",
+        ),
+        (
+            args.max_p95_gpu,
+            "p95 GPU residency",
+            summary.p95("gpu_active_percent"),
+            "%",
+        ),
+    ];
+    rules
+        .into_iter()
+        .filter_map(|(limit, label, value, unit)| {
+            limit.map(|limit| Outcome {
+                label,
+                value,
+                limit,
+                unit,
+            })
+        })
+        .collect()
+}
+
+pub fn format_report(source: &str, outcomes: &[Outcome]) -> String {
+    let failed = outcomes.iter().filter(|o| !o.passed()).count();
+    let mut lines = vec![if failed == 0 {
+        format!("mtop check passed for {}", source)
+    } else {
+        format!(
+            "mtop check failed for {}: {} of {} limits exceeded",
+            source,
+            failed,
+            outcomes.len()
+        )
+    }];
+    for outcome in outcomes {
+        lines.push(format!(
+            "  {}  {:<20} {:>10.2} {:<2} {} {:.2} {}",
+            if outcome.passed() { "PASS" } else { "FAIL" },
+            outcome.label,
+            outcome.value,
+            outcome.unit,
+            if outcome.passed() { "<=" } else { "> " },
+            outcome.limit,
+            outcome.unit
+        ));
+    }
+    lines.join("\n")
+}
+
+// Evaluates the limits and returns the exit code: 0 within limits, 1 when exceeded
+pub fn check(args: &CheckArgs, interval_ms: u64) -> Result<i32, Box<dyn std::error::Error>> {
+    let (source, summary) = match &args.recording {
+        Some(path) => (path.display().to_string(), load_summary(path)?),
+        None => {
+            let (tx, rx) = unbounded();
+            let running = spawn_collector(interval_ms, None, &tx);
+            drop(tx);
+            let result = run::measure(&args.command, &rx);
+            *running.lock().unwrap() = false;
+            let measurement = result?;
+            // A command that fails outright fails the check with its own status
+            if !measurement.status.success() {
+                eprintln!("'{}' failed", args.command.join(" "));
+                return Ok(run::exit_code(measurement.status));
+            }
+            (format!("'{}'", args.command.join(" ")), measurement.summary)
+        }
+    };
+
+    let outcomes = evaluate(args, &summary);
+    if outcomes.is_empty() {
+        return Err("no limits given, e.g. --max-avg-package-w 12".into());
+    }
+    println!("{}", format_report(&source, &outcomes));
+    Ok(if outcomes.iter().all(Outcome::passed) {
+        0
+    } else {
+        1
+    })
+}
+
+fn load_summary(path: &Path) -> Result<Summary, Box<dyn std::error::Error>> {
+    let samples = recording::load(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?
+        .samples;
+    if samples.is_empty() {
+        return Err(format!("{} has no samples", path.display()).into());
+    }
+    Ok(Summary::new(&samples))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // Only the recording, with no limits yet
+    fn args() -> CheckArgs {
+        CheckArgs {
+            recording: Some(PathBuf::from(recording::FIXTURE)),
+            max_avg_package_w: None,
+            max_avg_cpu_w: None,
+            max_avg_gpu_w: None,
+            max_peak_package_w: None,
+            max_energy_j: None,
+            max_p95_e_cpu: None,
+            max_p95_p_cpu: None,
+            max_p95_gpu: None,
+            command: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn compares_the_fixture_against_limits() {
+        let summary = Summary::new(&recording::fixture().samples);
+        let args = CheckArgs {
+            // 365 J over 22s
+            max_avg_package_w: Some(17.0),
+            max_peak_package_w: Some(25.0),
+            max_p95_p_cpu: Some(95.0),
+            ..args()
+        };
+        let outcomes = evaluate(&args, &summary);
+        let passed: Vec<(&str, bool)> = outcomes.iter().map(|o| (o.label, o.passed())).collect();
+        assert_eq!(
+            passed,
+            vec![
+                ("avg package power", true),
+                ("peak package power", false),
+                ("p95 P-CPU residency", true),
+            ]
+        );
+        assert_eq!(outcomes[1].value, 30.0);
+
+        assert_eq!(
+            format_report("session.rec", &outcomes),
+            [
+                "mtop check failed for session.rec: 1 of 3 limits exceeded",
+                "  PASS  avg package power         16.59 W  <= 17.00 W",
+                "  FAIL  peak package power        30.00 W  >  25.00 W",
+                "  PASS  p95 P-CPU residency       95.00 %  <= 95.00 %",
+            ]
+            .join("\n")
+        );
+        let within = &outcomes[..1];
+        assert_eq!(
+            format_report("session.rec", within).lines().next(),
+            Some("mtop check passed for session.rec")
+        );
+    }
+
+    #[test]
+    fn exits_with_one_when_a_limit_is_exceeded() {
+        let within = CheckArgs {
+            max_energy_j: Some(365.0),
+            ..args()
+        };
+        assert_eq!(check(&within, 1000).unwrap(), 0);
+        let exceeded = CheckArgs {
+            max_energy_j: Some(300.0),
+            max_avg_cpu_w: Some(5.0),
+            ..args()
+        };
+        assert_eq!(check(&exceeded, 1000).unwrap(), 1);
+        assert!(check(&args(), 1000).is_err());
+    }
+}
@@ -0,0 +1,299 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::alert::Rule;
+use crate::condition::Condition;
+use crate::config::{AlertRule, Config};
+use crate::statusline::{Format, Template};
+
+#[derive(Parser)]
+#[command(version, about = "Performance monitor for Apple Silicon")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Config file (defaults to $XDG_CONFIG_HOME/mtop/config.toml or ~/.config/mtop/config.toml)
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+
+    /// Sample interval in milliseconds
+    #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub interval: Option<u64>,
+
+    /// Initial chart window in seconds, used for axes, averages and peaks
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub history: Option<u64>,
+
+    /// Electricity price per kWh, used to estimate running cost
+    #[arg(long)]
+    pub price_per_kwh: Option<f64>,
+
+    /// Grid carbon intensity in gCO2/kWh, used to estimate emissions
+    #[arg(long)]
+    pub carbon_intensity: Option<f64>,
+
+    /// Write every sample as a CSV row to this file
+    #[arg(long, value_name = "PATH")]
+    pub csv: Option<PathBuf>,
+
+    /// Record the session to this file for `mtop play`, `mtop check` and `mtop diff`
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    /// Save the samples around each moment this holds, e.g. "package_w > 25" or
+    /// "thermal_pressure > Nominal"; may be given more than once
+    #[arg(long, value_name = "CONDITION")]
+    pub trigger: Vec<Condition>,
+
+    /// Seconds of samples before a trigger to include in its capture
+    #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+    pub pre_trigger: u64,
+
+    /// Seconds of samples after a trigger to include in its capture
+    #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+    pub post_trigger: u64,
+
+    /// Directory for triggered captures
+    #[arg(long, value_name = "DIR", default_value = ".")]
+    pub capture_dir: PathBuf,
+
+    /// Create a FIFO here and add a marker named by each line written to it,
+    /// e.g. `echo "epoch 3 start" > PATH`
+    #[arg(long, value_name = "PATH")]
+    pub markers: Option<PathBuf>,
+
+    /// Answer queries for the latest sample, a metric's history or summary statistics on a
+    /// Unix socket, e.g. `echo "history package_w 5m" | nc -U PATH`
+    #[arg(long, value_name = "PATH")]
+    pub socket: Option<PathBuf>,
+
+    /// Alert when this rule holds, e.g. "p_cpu_active > 90 for 30s" or "package_w > 20 avg 10s";
+    /// may be given more than once, alongside [[alerts]] in the config
+    #[arg(long, value_name = "RULE")]
+    pub alert: Vec<Rule>,
+
+    /// Permissions for the --socket file in octal; the default lets only root ask, 666 lets
+    /// anyone on the machine
+    #[arg(long, value_name = "MODE", default_value = "600", value_parser = parse_mode)]
+    pub socket_mode: u32,
+
+    /// Run this shell command when an alert fires or resolves, with the details in MTOP_ALERT_*
+    /// environment variables
+    #[arg(long, value_name = "COMMAND")]
+    pub alert_command: Option<String>,
+
+    /// Append alerts as they fire and resolve to this file
+    #[arg(long, value_name = "FILE")]
+    pub alert_log: Option<PathBuf>,
+
+    /// POST alerts as JSON to this http:// URL
+    #[arg(long, value_name = "URL")]
+    pub alert_webhook: Option<String>,
+
+    /// Print one line per sample instead of drawing the dashboard: a template such as
+    /// "P:{p_cpu}% G:{gpu}% {package_w:.1}W", or a preset (tmux, i3bar, waybar, xbar, swiftbar)
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "json")]
+    pub statusline: Option<Template>,
+
+    /// How to wrap status lines; presets choose their own
+    #[arg(long, value_enum, value_name = "FORMAT", requires = "statusline")]
+    pub statusline_format: Option<Format>,
+
+    /// Collect and export samples without drawing the dashboard
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Stream samples to stdout as JSON Lines instead of drawing the dashboard
+    #[arg(long)]
+    pub json: bool,
+
+    /// Serve Prometheus metrics on http://ADDR/metrics, e.g. 0.0.0.0:9090
+    #[arg(long, value_name = "ADDR")]
+    pub exporter: Option<String>,
+
+    /// Write InfluxDB line protocol to a file, udp://HOST:PORT or an http:// write URL
+    #[arg(long, value_name = "TARGET")]
+    pub influx: Option<String>,
+
+    /// Send gauges to a StatsD daemon at HOST:PORT over UDP
+    #[arg(long, value_name = "ADDR")]
+    pub statsd: Option<String>,
+
+    /// Print a summary of a single sample and exit
+    #[arg(long, conflicts_with = "samples")]
+    pub once: bool,
+
+    /// Print a summary averaged over N samples and exit
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    pub samples: Option<u32>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run a command and report the energy and utilisation it caused
+    Run(RunArgs),
+    /// Fail when a recording or a command exceeds energy and utilisation limits
+    Check(CheckArgs),
+    /// Replay a recording in the dashboard
+    Play(PlayArgs),
+    /// Compare two recordings with overlaid charts and a table of deltas
+    Diff(DiffArgs),
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// Measure the command this many times and report statistics across runs
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub repeat: u32,
+
+    /// Unmeasured runs before the measured ones, e.g. to warm caches
+    #[arg(long, default_value_t = 0)]
+    pub warmup: u32,
+
+    /// Measure idle power for this many seconds first and subtract it from the results
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub baseline: Option<u64>,
+
+    /// Command to run, after `--`
+    #[arg(required = true, trailing_var_arg = true, value_name = "COMMAND")]
+    pub command: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct PlayArgs {
+    /// File written by --record or --csv
+    #[arg(value_name = "FILE")]
+    pub file: PathBuf,
+}
+
+#[derive(Args)]
+pub struct DiffArgs {
+    /// The "before" recording, shown as A
+    #[arg(value_name = "A")]
+    pub a: PathBuf,
+
+    /// The "after" recording, shown as B
+    #[arg(value_name = "B")]
+    pub b: PathBuf,
+
+    /// Line the charts up at the first marker with this name in each recording
+    #[arg(long, value_name = "MARKER")]
+    pub align: Option<String>,
+
+    /// Print the table without showing the charts
+    #[arg(long)]
+    pub table: bool,
+}
+
+#[derive(Args)]
+pub struct CheckArgs {
+    /// Evaluate a file written by --record or --csv instead of running a command (no root needed)
+    #[arg(long, value_name = "FILE")]
+    pub recording: Option<PathBuf>,
+
+    /// Limit on average package power in watts
+    #[arg(long, value_name = "WATTS")]
+    pub max_avg_package_w: Option<f64>,
+
+    /// Limit on average CPU power in watts
+    #[arg(long, value_name = "WATTS")]
+    pub max_avg_cpu_w: Option<f64>,
+
+    /// Limit on average GPU power in watts
+    #[arg(long, value_name = "WATTS")]
+    pub max_avg_gpu_w: Option<f64>,
+
+    /// Limit on peak package power in watts
+    #[arg(long, value_name = "WATTS")]
+    pub max_peak_package_w: Option<f64>,
+
+    /// Limit on package energy in joules
+    #[arg(long, value_name = "JOULES")]
+    pub max_energy_j: Option<f64>,
+
+    /// Limit on 95th percentile E-cluster residency in percent
+    #[arg(long, value_name = "PERCENT")]
+    pub max_p95_e_cpu: Option<f64>,
+
+    /// Limit on 95th percentile P-cluster residency in percent
+    #[arg(long, value_name = "PERCENT")]
+    pub max_p95_p_cpu: Option<f64>,
+
+    /// Limit on 95th percentile GPU residency in percent
+    #[arg(long, value_name = "PERCENT")]
+    pub max_p95_gpu: Option<f64>,
+
+    /// Command to run, after `--`
+    #[arg(
+        trailing_var_arg = true,
+        value_name = "COMMAND",
+        required_unless_present = "recording",
+        conflicts_with = "recording"
+    )]
+    pub command: Vec<String>,
+}
+
+impl Cli {
+    // Working from recordings doesn't involve powermetrics
+    pub fn needs_root(&self) -> bool {
+        !matches!(
+            &self.command,
+            Some(Command::Check(CheckArgs {
+                recording: Some(_),
+                ..
+            })) | Some(Command::Play(_))
+                | Some(Command::Diff(_))
+        )
+    }
+
+    // How many samples to summarise before exiting, if mtop isn't running continuously
+    pub fn snapshot_samples(&self) -> Option<u32> {
+        if self.once {
+            Some(1)
+        } else {
+            self.samples
+        }
+    }
+
+    // Command-line flags take precedence over the config file, including after a reload
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(interval) = self.interval {
+            config.interval_ms = interval;
+        }
+        if let Some(history) = self.history {
+            config.history_secs = history;
+        }
+        if let Some(price) = self.price_per_kwh {
+            config.cost.price_per_kwh = Some(price);
+        }
+        if let Some(intensity) = self.carbon_intensity {
+            config.cost.carbon_g_per_kwh = Some(intensity);
+        }
+        if let Some(target) = &self.influx {
+            config.influx.target = Some(target.clone());
+        }
+        if let Some(addr) = &self.statsd {
+            config.statsd.addr = Some(addr.clone());
+        }
+        if let Some(command) = &self.alert_command {
+            config.notify.command = Some(command.clone());
+        }
+        if let Some(path) = &self.alert_log {
+            config.notify.log = Some(path.clone());
+        }
+        if let Some(url) = &self.alert_webhook {
+            config.notify.webhook = Some(url.clone());
+        }
+        config
+            .alerts
+            .extend(self.alert.iter().cloned().map(AlertRule::new));
+    }
+}
+
+fn parse_mode(text: &str) -> Result<u32, String> {
+    u32::from_str_radix(text, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+        .ok_or_else(|| format!("`{}` is not an octal file mode like 600", text))
+}
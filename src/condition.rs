@@ -0,0 +1,229 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::sample::{self, Sample};
+
+// A comparison of one sample field against a value, e.g. `package_w > 25` or `swap_used > 2GB`
+#[derive(Clone, PartialEq)]
+pub struct Condition {
+    field: usize,
+    op: Op,
+    value: f64,
+    text: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+// Two-character operators come first so `>=` isn't read as `>`
+const OPERATORS: [(&str, Op); 6] = [
+    (">=", Op::GreaterOrEqual),
+    ("<=", Op::LessOrEqual),
+    ("==", Op::Equal),
+    ("!=", Op::NotEqual),
+    (">", Op::Greater),
+    ("<", Op::Less),
+];
+
+impl Condition {
+    pub fn field(&self) -> usize {
+        self.field
+    }
+
+    pub fn value_of(&self, sample: &Sample) -> f64 {
+        sample.values()[self.field]
+    }
+
+    pub fn holds(&self, sample: &Sample) -> bool {
+        self.test(self.value_of(sample))
+    }
+
+    pub fn test(&self, value: f64) -> bool {
+        match self.op {
+            Op::Greater => value > self.value,
+            Op::GreaterOrEqual => value >= self.value,
+            Op::Less => value < self.value,
+            Op::LessOrEqual => value <= self.value,
+            Op::Equal => value == self.value,
+            Op::NotEqual => value != self.value,
+        }
+    }
+
+    // Like `test`, but with the limit moved `margin` the other way, so a value has to clear it
+    // by that much before the comparison stops holding
+    pub fn test_with_margin(&self, value: f64, margin: f64) -> bool {
+        match self.op {
+            Op::Greater => value > self.value - margin,
+            Op::GreaterOrEqual => value >= self.value - margin,
+            Op::Less => value < self.value + margin,
+            Op::LessOrEqual => value <= self.value + margin,
+            Op::Equal | Op::NotEqual => self.test(value),
+        }
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        let (position, symbol, op) = OPERATORS
+            .iter()
+            .find_map(|&(symbol, op)| text.find(symbol).map(|i| (i, symbol, op)))
+            .ok_or("expected a comparison such as `package_w > 25`")?;
+        Ok(Self {
+            field: resolve_field(text[..position].trim())?,
+            op,
+            value: parse_value(text[position + symbol.len()..].trim())?,
+            text: text.trim().to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+// Field names may leave off their unit suffix, e.g. `p_cpu_active`, `p_cpu` or `swap_used`
+pub fn resolve_field(name: &str) -> Result<usize, String> {
+    ["", "_percent", "_active_percent", "_bytes", "_level"]
+        .iter()
+        .find_map(|suffix| {
+            let full = format!("{}{}", name, suffix);
+            sample::FIELDS.iter().position(|field| *field == full)
+        })
+        .ok_or_else(|| format!("unknown metric `{}`", name))
+}
+
+// A number with an optional unit (%, W, mW, MHz, GHz, B, KB, MB, GB, TB) or a thermal pressure level
+pub fn parse_value(text: &str) -> Result<f64, String> {
+    if let Some(level) = sample::THERMAL_PRESSURE_LEVELS
+        .iter()
+        .position(|level| level.eq_ignore_ascii_case(text))
+    {
+        return Ok(level as f64);
+    }
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid value `{}`", text))?;
+    let scale = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "%" | "W" | "MHZ" | "B" => 1.0,
+        "MW" => 0.001,
+        "GHZ" => 1000.0,
+        "KB" => 1024.0,
+        "MB" => 1024.0 * 1024.0,
+        "GB" => 1024.0 * 1024.0 * 1024.0,
+        "TB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("unknown unit in `{}`", text)),
+    };
+    Ok(number * scale)
+}
+
+// Durations such as `30s`, `5m`, `1h` or `250ms`; a bare number is seconds
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration `{}`", text))?;
+    let seconds = match unit {
+        "" | "s" => number,
+        "ms" => number / 1000.0,
+        "m" => number * 60.0,
+        "h" => number * 3600.0,
+        _ => return Err(format!("unknown unit in `{}`", text)),
+    };
+    Duration::try_from_secs_f64(seconds).map_err(|_| format!("duration `{}` is too long", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn condition(text: &str) -> Condition {
+        text.parse().unwrap()
+    }
+
+    fn sample(package_w: f64, p_cpu_freq_mhz: f64) -> Sample {
+        let mut sample = Sample::from_values(UNIX_EPOCH, &[0.0; sample::FIELDS.len()]);
+        sample.package_w = package_w;
+        sample.p_cpu_freq_mhz = p_cpu_freq_mhz;
+        sample.memory_used = 6 << 30;
+        sample
+    }
+
+    #[test]
+    fn resolves_fields_without_suffixes() {
+        let field = |name: &str| sample::FIELDS[resolve_field(name).unwrap()];
+        assert_eq!(field("p_cpu"), "p_cpu_active_percent");
+        assert_eq!(field("p_cpu_active"), "p_cpu_active_percent");
+        assert_eq!(field("swap_used"), "swap_used_bytes");
+        assert_eq!(field("thermal_pressure"), "thermal_pressure_level");
+        assert_eq!(field("package_w"), "package_w");
+        assert!(resolve_field("watts").is_err());
+    }
+
+    #[test]
+    fn parses_values_with_units() {
+        assert_eq!(parse_value("25").unwrap(), 25.0);
+        assert_eq!(parse_value("90%").unwrap(), 90.0);
+        assert_eq!(parse_value("500mW").unwrap(), 0.5);
+        assert_eq!(parse_value("1.5 GHz").unwrap(), 1500.0);
+        assert_eq!(parse_value("2GB").unwrap(), 2.0 * 1024.0 * 1024.0 * 1024.0);
+        assert_eq!(parse_value("heavy").unwrap(), 2.0);
+        assert!(parse_value("5 parsecs").is_err());
+        assert!(parse_value("lots").is_err());
+    }
+
+    #[test]
+    fn parses_comparisons() {
+        let (calm, busy) = (sample(5.0, 1200.0), sample(20.0, 2400.0));
+        assert!(condition("package_w > 15").holds(&busy));
+        assert!(!condition("package_w > 15").holds(&calm));
+        // `>=` isn't read as `>` followed by `= 20`
+        assert!(condition("package_w >= 20").holds(&busy));
+        assert!(condition("p_cpu_freq_mhz < 2.5GHz").holds(&busy));
+        assert!(condition("memory_used <= 8GB").holds(&calm));
+        assert!(condition("thermal_pressure == Nominal").holds(&calm));
+        assert!(condition("gpu != 40").test(41.0));
+        assert_eq!(condition(" package_w>15 ").to_string(), "package_w>15");
+        assert!("package_w 15".parse::<Condition>().is_err());
+        assert!("watts > 15".parse::<Condition>().is_err());
+    }
+
+    #[test]
+    fn margins_move_the_limit_against_the_comparison() {
+        assert!(condition("package_w > 20").test_with_margin(16.0, 5.0));
+        assert!(!condition("package_w > 20").test_with_margin(15.0, 5.0));
+        assert!(condition("package_w < 20").test_with_margin(24.0, 5.0));
+        assert!(!condition("package_w == 20").test_with_margin(19.0, 5.0));
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("1.5m").unwrap(), Duration::from_secs(90));
+        assert_eq!(parse_duration("2h").unwrap(), Duration::from_secs(7200));
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("m").is_err());
+        assert!(parse_duration("99999999999999999999999s").is_err());
+    }
+}
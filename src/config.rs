@@ -0,0 +1,437 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Deserializer};
+use tui::style::Color;
+
+use crate::alert::{Rule, Severity};
+use crate::condition;
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub interval_ms: u64,
+    pub history_secs: u64,
+    pub colors: Colors,
+    pub layout: LayoutConfig,
+    pub units: Units,
+    pub thresholds: Thresholds,
+    pub alerts: Vec<AlertRule>,
+    pub notify: Notify,
+    pub cost: Cost,
+    pub influx: Influx,
+    pub statsd: Statsd,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            interval_ms: 1000,
+            history_secs: 120,
+            colors: Colors::default(),
+            layout: LayoutConfig::default(),
+            units: Units::default(),
+            thresholds: Thresholds::default(),
+            alerts: Vec::new(),
+            notify: Notify::default(),
+            cost: Cost::default(),
+            influx: Influx::default(),
+            statsd: Statsd::default(),
+        }
+    }
+}
+
+impl Config {
+    // Notifiers and the InfluxDB & StatsD sinks are only set up at startup, so a reloaded `new`
+    // keeps the running sections; returns those that changed and need a restart
+    pub fn keep_startup_sections(&self, new: &mut Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if new.notify != self.notify {
+            changed.push("[notify]");
+            new.notify = self.notify.clone();
+        }
+        if new.influx != self.influx {
+            changed.push("[influx]");
+            new.influx = self.influx.clone();
+        }
+        if new.statsd != self.statsd {
+            changed.push("[statsd]");
+            new.statsd = self.statsd.clone();
+        }
+        changed
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Colors {
+    #[serde(deserialize_with = "deserialize_color")]
+    pub e_cpu: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub p_cpu: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub ane: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub gpu: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub memory: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub cpu_power: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub gpu_power: Color,
+    #[serde(deserialize_with = "deserialize_color")]
+    pub package_power: Color,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            e_cpu: Color::Green,
+            p_cpu: Color::Yellow,
+            ane: Color::Blue,
+            gpu: Color::Magenta,
+            memory: Color::Cyan,
+            cpu_power: Color::Red,
+            gpu_power: Color::Red,
+            package_power: Color::Red,
+        }
+    }
+}
+
+// Percentages used to split the screen in `draw_ui`
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    pub top: u16,
+    pub utilization: u16,
+    pub memory: u16,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> Self {
+        Self {
+            top: 50,
+            utilization: 80,
+            memory: 50,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum PowerUnit {
+    #[serde(rename = "W")]
+    Watts,
+    #[serde(rename = "mW")]
+    Milliwatts,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub enum MemoryUnit {
+    #[serde(rename = "GB")]
+    Gigabytes,
+    #[serde(rename = "MB")]
+    Megabytes,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Units {
+    pub power: PowerUnit,
+    pub memory: MemoryUnit,
+}
+
+impl Default for Units {
+    fn default() -> Self {
+        Self {
+            power: PowerUnit::Watts,
+            memory: MemoryUnit::Gigabytes,
+        }
+    }
+}
+
+impl Units {
+    pub fn format_power(&self, watts: f64) -> String {
+        match self.power {
+            PowerUnit::Watts => format!("{:.2} W", watts),
+            PowerUnit::Milliwatts => format!("{:.0} mW", watts * 1000.0),
+        }
+    }
+
+    pub fn format_memory(&self, bytes: u64) -> String {
+        match self.memory {
+            MemoryUnit::Gigabytes => format!("{:.2} GB", bytes as f64 / 1024.0 / 1024.0 / 1024.0),
+            MemoryUnit::Megabytes => format!("{:.0} MB", bytes as f64 / 1024.0 / 1024.0),
+        }
+    }
+}
+
+// Charts whose current value exceeds their threshold get a highlighted border
+#[derive(Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Thresholds {
+    pub e_cpu: Option<f64>,
+    pub p_cpu: Option<f64>,
+    pub ane: Option<f64>,
+    pub gpu: Option<f64>,
+    pub memory: Option<f64>,
+    pub cpu_w: Option<f64>,
+    pub gpu_w: Option<f64>,
+    pub package_w: Option<f64>,
+}
+
+// An `[[alerts]]` entry; hysteresis is in the metric's units, e.g. `10` or `"256MB"`
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertRule {
+    #[serde(deserialize_with = "deserialize_rule")]
+    pub rule: Rule,
+    #[serde(default = "default_severity")]
+    pub severity: Severity,
+    #[serde(default, deserialize_with = "deserialize_amount")]
+    pub hysteresis: f64,
+}
+
+impl AlertRule {
+    pub fn new(rule: Rule) -> Self {
+        Self {
+            rule,
+            severity: default_severity(),
+            hysteresis: 0.0,
+        }
+    }
+}
+
+fn default_severity() -> Severity {
+    Severity::Warning
+}
+
+// Where alerts go when they fire and resolve: a shell command, a log file and an http:// webhook
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Notify {
+    pub command: Option<String>,
+    pub log: Option<PathBuf>,
+    pub webhook: Option<String>,
+    pub timeout_ms: u64,
+    pub retries: u32,
+}
+
+impl Default for Notify {
+    fn default() -> Self {
+        Self {
+            command: None,
+            log: None,
+            webhook: None,
+            timeout_ms: 5000,
+            retries: 3,
+        }
+    }
+}
+
+// Turns integrated package energy into running cost and emissions estimates
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cost {
+    pub price_per_kwh: Option<f64>,
+    pub currency: String,
+    pub carbon_g_per_kwh: Option<f64>,
+}
+
+impl Default for Cost {
+    fn default() -> Self {
+        Self {
+            price_per_kwh: None,
+            currency: "$".to_string(),
+            carbon_g_per_kwh: None,
+        }
+    }
+}
+
+// Where InfluxDB line protocol goes: a file path, udp://host:port or an http:// write URL
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Influx {
+    pub target: Option<String>,
+    pub token: Option<String>,
+    pub batch_size: usize,
+    pub flush_interval_ms: u64,
+    pub retries: u32,
+}
+
+impl Default for Influx {
+    fn default() -> Self {
+        Self {
+            target: None,
+            token: None,
+            batch_size: 10,
+            flush_interval_ms: 10000,
+            retries: 3,
+        }
+    }
+}
+
+// Gauges sent to a StatsD daemon; tags use the DogStatsD `|#tag` extension
+#[derive(Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Statsd {
+    pub addr: Option<String>,
+    pub prefix: String,
+    pub tags: Vec<String>,
+    pub flush_interval_ms: u64,
+}
+
+impl Default for Statsd {
+    fn default() -> Self {
+        Self {
+            addr: None,
+            prefix: "mtop".to_string(),
+            tags: Vec::new(),
+            flush_interval_ms: 10000,
+        }
+    }
+}
+
+pub fn exceeds(threshold: Option<f64>, value: f64) -> bool {
+    threshold.is_some_and(|limit| value > limit)
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    let base = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("mtop").join("config.toml"))
+}
+
+pub fn load(path: &Path) -> Result<Config, io::Error> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(e),
+    };
+    parse(&contents)
+}
+
+pub fn parse(contents: &str) -> Result<Config, io::Error> {
+    let config: Config = toml::from_str(contents)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    if config.interval_ms == 0 || config.history_secs == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "interval_ms and history_secs must be greater than zero",
+        ));
+    }
+    if config.influx.batch_size == 0 || config.influx.flush_interval_ms == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "influx.batch_size and influx.flush_interval_ms must be greater than zero",
+        ));
+    }
+    let layout = &config.layout;
+    if [layout.top, layout.utilization, layout.memory]
+        .iter()
+        .any(|percent| !(1..=99).contains(percent))
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "layout percentages must be between 1 and 99",
+        ));
+    }
+    Ok(config)
+}
+
+// Polls the config file's modification time so edits are picked up without a restart
+pub struct ConfigWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf) -> Self {
+        let modified = modified_time(&path);
+        Self { path, modified }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn poll(&mut self) -> Option<Result<Config, io::Error>> {
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(load(&self.path))
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn deserialize_rule<'de, D>(deserializer: D) -> Result<Rule, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+fn deserialize_amount<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        Number(f64),
+        Text(String),
+    }
+    match Amount::deserialize(deserializer)? {
+        Amount::Number(number) => Ok(number),
+        Amount::Text(text) => condition::parse_value(&text).map_err(serde::de::Error::custom),
+    }
+}
+
+fn deserialize_color<'de, D>(deserializer: D) -> Result<Color, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let name = String::deserialize(deserializer)?;
+    parse_color(&name).ok_or_else(|| serde::de::Error::custom(format!("unknown colour `{}`", name)))
+}
+
+fn parse_color(name: &str) -> Option<Color> {
+    if let Some(hex) = name.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::Rgb(channel(0)?, channel(2)?, channel(4)?));
+    }
+    let color = match name.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return None,
+    };
+    Some(color)
+}
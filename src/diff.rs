@@ -0,0 +1,396 @@
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crossterm::event::{self, Event, KeyCode};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use serde_json::{json, Value};
+use tui::backend::CrosstermBackend;
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Color, Style};
+use tui::text::{Span, Spans};
+use tui::widgets::canvas::{Canvas, Line};
+use tui::widgets::{Block, Borders, Paragraph};
+use tui::{Frame, Terminal};
+
+use crate::cli::DiffArgs;
+use crate::config::Units;
+use crate::recording::{self, Recording};
+use crate::sample;
+use crate::summary::{self, Summary};
+use crate::{chart_block, paint_trace, ChartPoint};
+
+const COLOR_A: Color = Color::Cyan;
+const COLOR_B: Color = Color::Magenta;
+// Traces are averaged down to about this many points so long recordings stay quick to draw
+const MAX_POINTS: usize = 400;
+
+struct Session {
+    name: String,
+    recording: Recording,
+    summary: Summary,
+    // Time zero on the charts: the start of the recording or the marker it's aligned by
+    origin: SystemTime,
+}
+
+impl Session {
+    fn seconds(&self, time: SystemTime) -> f64 {
+        match time.duration_since(self.origin) {
+            Ok(after) => after.as_secs_f64(),
+            Err(before) => -before.duration().as_secs_f64(),
+        }
+    }
+
+    // First and last moments on the charts' time axis
+    fn span(&self) -> (f64, f64) {
+        let last = self.recording.samples.last().map(|sample| sample.timestamp);
+        (
+            self.seconds(self.recording.start),
+            self.seconds(last.unwrap_or(self.recording.start)),
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Percent,
+    Power,
+    Memory,
+}
+
+// Charts in reading order, two to a row
+const PANELS: [(&str, &str, Kind); 8] = [
+    ("E-CPU Usage", "e_cpu_active_percent", Kind::Percent),
+    ("P-CPU Usage", "p_cpu_active_percent", Kind::Percent),
+    ("GPU Usage", "gpu_active_percent", Kind::Percent),
+    ("ANE Power", "ane_w", Kind::Power),
+    ("CPU Power", "cpu_w", Kind::Power),
+    ("GPU Power", "gpu_w", Kind::Power),
+    ("Package Power", "package_w", Kind::Power),
+    ("Memory Used", "memory_used_bytes", Kind::Memory),
+];
+
+// Compares two recordings: overlaid charts until `q`, then a table of deltas on stdout
+pub fn diff(args: &DiffArgs, units: &Units, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let a = load(&args.a, args.align.as_deref())?;
+    let b = load(&args.b, args.align.as_deref())?;
+    if !args.table && !json {
+        show_charts(&a, &b, args.align.as_deref(), units)?;
+    }
+
+    let rows = compare(&a.summary, &b.summary);
+    if json {
+        let metrics: Vec<Value> = rows
+            .iter()
+            .map(|row| {
+                json!({
+                    "name": row.name,
+                    "a": row.a,
+                    "b": row.b,
+                    "delta": row.b - row.a,
+                    "percent": row.percent(),
+                })
+            })
+            .collect();
+        let record = json!({
+            "a": session_json(&a),
+            "b": session_json(&b),
+            "metrics": metrics,
+        });
+        println!("{}", record);
+    } else {
+        println!("\n Comparing {} (A) with {} (B):\n", a.name, b.name);
+        println!("{}", format_table(&rows));
+    }
+    Ok(())
+}
+
+fn load(path: &Path, align: Option<&str>) -> Result<Session, Box<dyn std::error::Error>> {
+    let recording =
+        recording::load(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if recording.samples.is_empty() {
+        return Err(format!("{} has no samples", path.display()).into());
+    }
+    let origin = match align {
+        Some(name) => recording
+            .markers
+            .iter()
+            .find(|marker| marker.name == name)
+            .map(|marker| marker.timestamp)
+            .ok_or_else(|| format!("{} has no marker named `{}`", path.display(), name))?,
+        None => recording.start,
+    };
+    let summary = Summary::new(&recording.samples);
+    Ok(Session {
+        name: path.display().to_string(),
+        recording,
+        summary,
+        origin,
+    })
+}
+
+fn session_json(session: &Session) -> Value {
+    let mut record = summary::info_json(&session.recording.info);
+    record["file"] = json!(session.name);
+    record["start"] = json!(sample::format_timestamp(session.recording.start));
+    record
+}
+
+// One line of the comparison table
+struct Row {
+    name: &'static str,
+    label: &'static str,
+    a: f64,
+    b: f64,
+}
+
+impl Row {
+    // Relative change from A to B, undefined when A is zero
+    fn percent(&self) -> Option<f64> {
+        (self.a != 0.0).then(|| (self.b - self.a) / self.a.abs() * 100.0)
+    }
+}
+
+// JSON name, table label and how to read it from a summary
+type Metric = (&'static str, &'static str, fn(&Summary) -> f64);
+
+fn compare(a: &Summary, b: &Summary) -> Vec<Row> {
+    let metrics: [Metric; 18] = [
+        ("duration_s", "Duration (s)", |s| s.seconds),
+        ("avg_e_cpu_percent", "Avg E-CPU (%)", |s| {
+            s.mean("e_cpu_active_percent")
+        }),
+        ("avg_p_cpu_percent", "Avg P-CPU (%)", |s| {
+            s.mean("p_cpu_active_percent")
+        }),
+        ("avg_gpu_percent", "Avg GPU (%)", |s| {
+            s.mean("gpu_active_percent")
+        }),
+        ("avg_cpu_w", "Avg CPU (W)", |s| s.mean("cpu_w")),
+        ("peak_cpu_w", "Peak CPU (W)", |s| s.max("cpu_w")),
+        ("avg_gpu_w", "Avg GPU (W)", |s| s.mean("gpu_w")),
+        ("peak_gpu_w", "Peak GPU (W)", |s| s.max("gpu_w")),
+        ("avg_ane_w", "Avg ANE (W)", |s| s.mean("ane_w")),
+        ("peak_ane_w", "Peak ANE (W)", |s| s.max("ane_w")),
+        ("avg_package_w", "Avg Package (W)", |s| s.mean("package_w")),
+        ("peak_package_w", "Peak Package (W)", |s| s.max("package_w")),
+        ("energy_j", "Energy (J)", |s| s.energy.package),
+        ("cpu_energy_j", "CPU Energy (J)", |s| s.energy.cpu),
+        ("gpu_energy_j", "GPU Energy (J)", |s| s.energy.gpu),
+        ("ane_energy_j", "ANE Energy (J)", |s| s.energy.ane),
+        ("peak_memory_gb", "Peak Memory (GB)", |s| {
+            s.max("memory_used_bytes") / 1024.0 / 1024.0 / 1024.0
+        }),
+        ("throttled_s", "Throttled (s)", |s| s.throttled_seconds),
+    ];
+    metrics
+        .iter()
+        .map(|&(name, label, value)| Row {
+            name,
+            label,
+            a: value(a),
+            b: value(b),
+        })
+        .collect()
+}
+
+fn format_table(rows: &[Row]) -> String {
+    let mut lines = vec![format!(
+        "   {:<18} {:>10} {:>10} {:>10} {:>9}",
+        "", "A", "B", "delta", "delta %"
+    )];
+    for row in rows {
+        let percent = match row.percent() {
+            Some(percent) => format!("{:+.1}%", percent),
+            None => "n/a".to_string(),
+        };
+        lines.push(format!(
+            "   {:<18} {:>10.3} {:>10.3} {:>+10.3} {:>9}",
+            row.label,
+            row.a,
+            row.b,
+            row.b - row.a,
+            percent
+        ));
+    }
+    lines.join("\n")
+}
+
+fn show_charts(
+    a: &Session,
+    b: &Session,
+    align: Option<&str>,
+    units: &Units,
+) -> Result<(), Box<dyn std::error::Error>> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let result = (|| -> io::Result<()> {
+        loop {
+            terminal.draw(|f| draw(f, a, b, align, units))?;
+            if event::poll(Duration::from_millis(250))? {
+                if let Event::Key(key) = event::read()? {
+                    if matches!(
+                        key.code,
+                        KeyCode::Char('q') | KeyCode::Char('Q') | KeyCode::Esc
+                    ) {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    })();
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    Ok(result?)
+}
+
+fn draw(
+    f: &mut Frame<CrosstermBackend<io::Stdout>>,
+    a: &Session,
+    b: &Session,
+    align: Option<&str>,
+    units: &Units,
+) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+        .split(f.size());
+
+    let describe = |session: &Session| {
+        format!(
+            "{} ({}, {})",
+            session.name,
+            session.recording.info.name,
+            sample::format_timestamp(session.recording.start)
+        )
+    };
+    let legend = Paragraph::new(Spans::from(vec![
+        Span::styled(" A: ", Style::default().fg(COLOR_A)),
+        Span::raw(describe(a)),
+        Span::styled("   B: ", Style::default().fg(COLOR_B)),
+        Span::raw(describe(b)),
+        Span::raw(match align {
+            Some(name) => format!("   (aligned at marker `{}`, q to quit)", name),
+            None => "   (aligned from start, q to quit)".to_string(),
+        }),
+    ]))
+    .block(Block::default().borders(Borders::ALL));
+    f.render_widget(legend, rows[0]);
+
+    let grid = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Ratio(1, 4); 4].as_ref())
+        .split(rows[1]);
+    let (a_start, a_end) = a.span();
+    let (b_start, b_end) = b.span();
+    let x_bounds = [
+        a_start.min(b_start),
+        a_end.max(b_end).max(a_start.min(b_start) + 1.0),
+    ];
+    for (i, &(title, field, kind)) in PANELS.iter().enumerate() {
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(50); 2].as_ref())
+            .split(grid[i / 2]);
+        render_panel(f, columns[i % 2], title, field, kind, a, b, x_bounds, units);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_panel(
+    f: &mut Frame<CrosstermBackend<io::Stdout>>,
+    area: Rect,
+    title: &str,
+    field: &str,
+    kind: Kind,
+    a: &Session,
+    b: &Session,
+    x_bounds: [f64; 2],
+    units: &Units,
+) {
+    let format = |value: f64| match kind {
+        Kind::Percent => format!("{:.1}%", value),
+        Kind::Power => units.format_power(value),
+        Kind::Memory => units.format_memory(value as u64),
+    };
+    let (mean_a, mean_b) = (a.summary.mean(field), b.summary.mean(field));
+    let change = if mean_a != 0.0 {
+        format!(" ({:+.1}%)", (mean_b - mean_a) / mean_a.abs() * 100.0)
+    } else {
+        String::new()
+    };
+    let label = format!("avg A {}  B {}{} ", format(mean_a), format(mean_b), change);
+
+    // Percentages keep a fixed axis; everything else is scaled to the higher peak
+    let scale = match kind {
+        Kind::Percent => 1.0,
+        _ => {
+            let peak = a.summary.max(field).max(b.summary.max(field));
+            if peak > 0.0 {
+                100.0 / peak
+            } else {
+                0.0
+            }
+        }
+    };
+    let trace_a = trace(a, field, scale);
+    let trace_b = trace(b, field, scale);
+    let markers: Vec<(f64, Color)> = [(a, COLOR_A), (b, COLOR_B)]
+        .iter()
+        .flat_map(|&(session, color)| {
+            session
+                .recording
+                .markers
+                .iter()
+                .map(move |marker| (session.seconds(marker.timestamp), color))
+        })
+        .collect();
+
+    let canvas = Canvas::default()
+        .block(chart_block(&format!("\n {}", title), &label, false))
+        .x_bounds(x_bounds)
+        .y_bounds([0.0, 100.0])
+        .paint(move |ctx| {
+            for &(x, color) in &markers {
+                ctx.draw(&Line {
+                    x1: x,
+                    y1: 0.0,
+                    x2: x,
+                    y2: 100.0,
+                    color,
+                });
+            }
+            paint_trace(ctx, &trace_a, COLOR_A, |point| point.avg);
+            paint_trace(ctx, &trace_b, COLOR_B, |point| point.avg);
+        });
+    f.render_widget(canvas, area);
+}
+
+// Seconds from the session's origin against the field's value, averaged in even chunks
+fn trace(session: &Session, field: &str, scale: f64) -> Vec<ChartPoint> {
+    let recording = &session.recording;
+    let index = summary::field_index(field);
+    let chunk = recording.samples.len().div_ceil(MAX_POINTS).max(1);
+    recording
+        .samples
+        .chunks(chunk)
+        .map(|samples| {
+            let values: Vec<f64> = samples.iter().map(|s| s.values()[index]).collect();
+            let last = samples[samples.len() - 1].timestamp;
+            ChartPoint {
+                x: session.seconds(last),
+                min: values.iter().copied().fold(f64::INFINITY, f64::min) * scale,
+                avg: values.iter().sum::<f64>() / values.len() as f64 * scale,
+                max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max) * scale,
+            }
+        })
+        .collect()
+}
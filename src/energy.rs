@@ -0,0 +1,145 @@
+use std::time::Duration;
+
+use crate::config::Cost;
+use crate::sample::Sample;
+
+const JOULES_PER_KWH: f64 = 3_600_000.0;
+
+// Joules per component
+#[derive(Clone, Copy, Default)]
+pub struct Energy {
+    pub cpu: f64,
+    pub gpu: f64,
+    pub ane: f64,
+    pub package: f64,
+}
+
+impl Energy {
+    pub fn add_sample(&mut self, sample: &Sample) {
+        let seconds = sample.elapsed_ms / 1000.0;
+        self.cpu += sample.cpu_w * seconds;
+        self.gpu += sample.gpu_w * seconds;
+        self.ane += sample.ane_w * seconds;
+        self.package += sample.package_w * seconds;
+    }
+}
+
+// Integrates power samples over each sample's elapsed time; durations are sampled time, not wall time
+pub struct EnergyMeter {
+    total: Energy,
+    since_mark: Energy,
+    seconds: f64,
+    mark_seconds: Option<f64>,
+}
+
+impl EnergyMeter {
+    pub fn new() -> Self {
+        Self {
+            total: Energy::default(),
+            since_mark: Energy::default(),
+            seconds: 0.0,
+            mark_seconds: None,
+        }
+    }
+
+    pub fn record(&mut self, sample: &Sample) {
+        self.total.add_sample(sample);
+        self.since_mark.add_sample(sample);
+        self.seconds += sample.elapsed_ms / 1000.0;
+        if let Some(seconds) = &mut self.mark_seconds {
+            *seconds += sample.elapsed_ms / 1000.0;
+        }
+    }
+
+    pub fn reset_mark(&mut self) {
+        self.mark_seconds = Some(0.0);
+        self.since_mark = Energy::default();
+    }
+
+    pub fn total(&self) -> &Energy {
+        &self.total
+    }
+
+    pub fn since_mark(&self) -> &Energy {
+        &self.since_mark
+    }
+
+    fn average_package_w(&self) -> f64 {
+        if self.seconds > 0.0 {
+            self.total.package / self.seconds
+        } else {
+            0.0
+        }
+    }
+
+    // Package energy so far priced and converted to emissions, projected per day at `package_w`,
+    // e.g. the average over the span the dashboard summarises
+    pub fn cost_label(&self, cost: &Cost, package_w: f64) -> Option<String> {
+        let kwh = self.total.package / JOULES_PER_KWH;
+        let kwh_per_day = package_w * 24.0 / 1000.0;
+        let mut parts = Vec::new();
+        if let Some(price) = cost.price_per_kwh {
+            parts.push(format!(
+                "Cost: {}{:.4} ({}{:.2}/day)",
+                cost.currency,
+                kwh * price,
+                cost.currency,
+                kwh_per_day * price
+            ));
+        }
+        if let Some(intensity) = cost.carbon_g_per_kwh {
+            parts.push(format!(
+                "CO2: {:.2} g ({:.0} g/day)",
+                kwh * intensity,
+                kwh_per_day * intensity
+            ));
+        }
+        (!parts.is_empty()).then(|| parts.join("  "))
+    }
+
+    pub fn summary(&self, cost: &Cost) -> String {
+        let mut text = format!(
+            "Energy since start ({}):\n{}",
+            format_elapsed(Duration::from_secs_f64(self.seconds)),
+            format_table(&self.total)
+        );
+        if let Some(seconds) = self.mark_seconds {
+            text.push_str(&format!(
+                "\nEnergy since mark ({}):\n{}",
+                format_elapsed(Duration::from_secs_f64(seconds)),
+                format_table(&self.since_mark)
+            ));
+        }
+        if let Some(label) = self.cost_label(cost, self.average_package_w()) {
+            text.push_str(&format!("\n{}", label));
+        }
+        text
+    }
+}
+
+pub fn format_energy(joules: f64) -> String {
+    let energy = if joules >= 1000.0 {
+        format!("{:.2} kJ", joules / 1000.0)
+    } else {
+        format!("{:.1} J", joules)
+    };
+    format!("{} / {:.3} Wh", energy, joules / 3600.0)
+}
+
+fn format_table(energy: &Energy) -> String {
+    [
+        ("CPU", energy.cpu),
+        ("GPU", energy.gpu),
+        ("ANE", energy.ane),
+        ("Package", energy.package),
+    ]
+    .iter()
+    .map(|(name, joules)| format!("  {:<8} {}", name, format_energy(*joules)))
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+pub fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
@@ -0,0 +1,164 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+
+use crate::marker::Marker;
+use crate::sample::{self, Sample};
+use crate::summary;
+use crate::AppleSiliconInfo;
+
+// A destination every collected sample is written to, alongside or instead of the dashboard
+pub trait Sink {
+    fn write(&mut self, sample: &Sample) -> io::Result<()>;
+
+    // Formats without a place for markers skip them
+    fn mark(&mut self, _marker: &Marker) -> io::Result<()> {
+        Ok(())
+    }
+
+    // A line for the dashboard's info panel, for sinks with something to report
+    fn status(&self) -> Option<String> {
+        None
+    }
+}
+
+// A socket to send datagrams to `addr` from, bound to any local address of the same family
+pub fn udp_socket_for(addr: &SocketAddr) -> io::Result<UdpSocket> {
+    let local: SocketAddr = if addr.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    UdpSocket::bind(local)
+}
+
+pub struct CsvSink {
+    out: BufWriter<File>,
+}
+
+impl CsvSink {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        // Markers get rows of their own, with only the timestamp and the last column filled in
+        writeln!(out, "timestamp,{},marker", sample::FIELDS.join(","))?;
+        out.flush()?;
+        Ok(Self { out })
+    }
+}
+
+impl Sink for CsvSink {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let values: Vec<String> = sample.values().iter().map(|v| v.to_string()).collect();
+        writeln!(
+            self.out,
+            "{},{},",
+            sample::format_timestamp(sample.timestamp),
+            values.join(",")
+        )?;
+        // Rows are flushed as they come so the file can be followed while mtop runs
+        self.out.flush()
+    }
+
+    fn mark(&mut self, marker: &Marker) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{}{},{}",
+            sample::format_timestamp(marker.timestamp),
+            ",".repeat(sample::FIELDS.len()),
+            csv_text(&marker.name)
+        )?;
+        self.out.flush()
+    }
+}
+
+// Quotes text holding commas or quotes, doubling the quotes
+fn csv_text(text: &str) -> String {
+    if text.contains([',', '"']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+// JSON Lines on stdout: a header record describing the machine, then one record per sample
+pub struct JsonSink {
+    out: io::Stdout,
+}
+
+impl JsonSink {
+    pub fn new(info: &AppleSiliconInfo, interval_ms: u64) -> io::Result<Self> {
+        let mut sink = Self { out: io::stdout() };
+        let mut header = summary::info_json(info);
+        header["type"] = json!("header");
+        header["interval_ms"] = json!(interval_ms);
+        sink.write_record(&header)?;
+        Ok(sink)
+    }
+
+    fn write_record(&mut self, record: &Value) -> io::Result<()> {
+        let mut out = self.out.lock();
+        writeln!(out, "{}", record)?;
+        out.flush()
+    }
+}
+
+// A sample as a JSON object keyed by the CSV column names
+pub fn sample_json(sample: &Sample) -> Value {
+    let mut record = Map::new();
+    record.insert("type".to_string(), json!("sample"));
+    record.insert(
+        "timestamp".to_string(),
+        json!(sample::format_timestamp(sample.timestamp)),
+    );
+    for (name, value) in sample::FIELDS.iter().zip(sample.values()) {
+        // Byte counts and levels are whole numbers, so keep them integral for consumers
+        let value = if name.ends_with("_bytes") || name.ends_with("_level") {
+            json!(value as u64)
+        } else {
+            json!(value)
+        };
+        record.insert(name.to_string(), value);
+    }
+    Value::Object(record)
+}
+
+impl Sink for JsonSink {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        self.write_record(&sample_json(sample))
+    }
+
+    fn mark(&mut self, marker: &Marker) -> io::Result<()> {
+        self.write_record(&json!({
+            "type": "marker",
+            "timestamp": sample::format_timestamp(marker.timestamp),
+            "name": marker.name,
+        }))
+    }
+}
+
+// Writes to every sink, dropping any that fail and returning the last error
+pub fn write_all(sinks: &mut Vec<Box<dyn Sink>>, sample: &Sample) -> Option<io::Error> {
+    each_sink(sinks, |sink| sink.write(sample))
+}
+
+pub fn mark_all(sinks: &mut Vec<Box<dyn Sink>>, marker: &Marker) -> Option<io::Error> {
+    each_sink(sinks, |sink| sink.mark(marker))
+}
+
+fn each_sink(
+    sinks: &mut Vec<Box<dyn Sink>>,
+    mut action: impl FnMut(&mut Box<dyn Sink>) -> io::Result<()>,
+) -> Option<io::Error> {
+    let mut error = None;
+    sinks.retain_mut(|sink| match action(sink) {
+        Ok(()) => true,
+        Err(e) => {
+            error = Some(e);
+            false
+        }
+    });
+    error
+}
@@ -0,0 +1,303 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::energy::Energy;
+use crate::sample::{self, Sample};
+use crate::summary::{self, Summary};
+use crate::throttle::{Domain, ThrottleDetector};
+use crate::timeseries::TimeSeries;
+
+const FIELD_COUNT: usize = sample::FIELDS.len();
+
+// Every sample field's tiered history, kept once and read by both the dashboard and the query API
+pub struct History {
+    series: Vec<TimeSeries<f64>>,
+    // Whole samples, the latest one and as many before it as `keep` asks for
+    samples: VecDeque<Sample>,
+    keep: Duration,
+    // The latest sample's timestamp and where it sits on the history's clock
+    clock: Option<(SystemTime, Instant)>,
+    pub throttle: ThrottleDetector,
+}
+
+pub type SharedHistory = Arc<Mutex<History>>;
+
+impl History {
+    pub fn new() -> Self {
+        Self {
+            series: (0..FIELD_COUNT).map(|_| TimeSeries::new()).collect(),
+            samples: VecDeque::new(),
+            keep: Duration::ZERO,
+            clock: None,
+            throttle: ThrottleDetector::new(),
+        }
+    }
+
+    pub fn shared() -> SharedHistory {
+        Arc::new(Mutex::new(Self::new()))
+    }
+
+    // Starts over, still keeping as many whole samples
+    pub fn clear(&mut self) {
+        *self = Self {
+            keep: self.keep,
+            ..Self::new()
+        };
+    }
+
+    // Keeps whole samples from at least `span` before the latest, e.g. for triggered captures
+    pub fn keep_samples(&mut self, span: Duration) {
+        self.keep = self.keep.max(span);
+    }
+
+    // Times come from the caller so recordings can be replayed on their own clock
+    pub fn push(&mut self, sample: &Sample, time: Instant) {
+        let weight = sample.elapsed_ms / 1000.0;
+        for (series, value) in self.series.iter_mut().zip(sample.values()) {
+            series.push_at(time, value, weight);
+        }
+        self.throttle.update(sample);
+        self.clock = Some((sample.timestamp, time));
+
+        self.samples.push_back(*sample);
+        while let Some(oldest) = self.samples.front() {
+            let age = sample
+                .timestamp
+                .duration_since(oldest.timestamp)
+                .unwrap_or_default();
+            if age <= self.keep {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn series(&self, field: usize) -> &TimeSeries<f64> {
+        &self.series[field]
+    }
+
+    pub fn latest(&self) -> Option<&Sample> {
+        self.samples.back()
+    }
+
+    // Kept samples from up to `span` before `sample`, oldest first
+    pub fn samples_before(&self, sample: &Sample, span: Duration) -> Vec<Sample> {
+        self.samples
+            .iter()
+            .filter(|earlier| earlier.timestamp < sample.timestamp)
+            .filter(|earlier| {
+                sample
+                    .timestamp
+                    .duration_since(earlier.timestamp)
+                    .is_ok_and(|age| age <= span)
+            })
+            .copied()
+            .collect()
+    }
+
+    // Wall-clock time of a point on the history's clock
+    pub fn timestamp(&self, time: Instant) -> SystemTime {
+        let Some((timestamp, at)) = self.clock else {
+            return SystemTime::UNIX_EPOCH;
+        };
+        if time >= at {
+            timestamp.checked_add(time - at)
+        } else {
+            timestamp.checked_sub(at - time)
+        }
+        .unwrap_or(SystemTime::UNIX_EPOCH)
+    }
+
+    // Likely throttling intervals on the history's clock
+    pub fn throttled(&self, domain: Domain) -> Vec<(Instant, Instant)> {
+        let Some((timestamp, time)) = self.clock else {
+            return Vec::new();
+        };
+        let on_clock =
+            |at: SystemTime| time.checked_sub(timestamp.duration_since(at).unwrap_or_default());
+        self.throttle
+            .intervals
+            .iter()
+            .filter(|interval| interval.domain == domain)
+            .filter_map(|interval| Some((on_clock(interval.start)?, on_clock(interval.end)?)))
+            .collect()
+    }
+
+    // The window ending at `now` summarised from the rollups, or None if it has no samples
+    pub fn summary(&self, now: Instant, window: Duration) -> Option<Summary> {
+        let points = self.series[0].points(now, window);
+        if points.is_empty() {
+            return None;
+        }
+        let seconds: f64 = points.iter().map(|point| point.weight).sum();
+
+        let mut mean = [0.0; FIELD_COUNT];
+        let mut p50 = [0.0; FIELD_COUNT];
+        let mut p95 = [0.0; FIELD_COUNT];
+        let mut max = [0.0; FIELD_COUNT];
+        for (i, series) in self.series.iter().enumerate() {
+            let stats = series.stats(now, window);
+            mean[i] = stats.mean;
+            p50[i] = stats.p50;
+            p95[i] = stats.p95;
+            max[i] = stats.max;
+        }
+        // Mean power over the weighted time is the same integral energy totals take
+        let joules = |field: &str| mean[summary::field_index(field)] * seconds;
+        let energy = Energy {
+            cpu: joules("cpu_w"),
+            gpu: joules("gpu_w"),
+            ane: joules("ane_w"),
+            package: joules("package_w"),
+        };
+
+        let since = now
+            .checked_sub(window)
+            .map_or(SystemTime::UNIX_EPOCH, |start| self.timestamp(start));
+
+        Some(Summary {
+            samples: points.iter().map(|point| point.count as usize).sum(),
+            seconds,
+            mean,
+            p50,
+            p95,
+            max,
+            energy,
+            throttled_intervals: self.throttle.count_since(since),
+            throttled_seconds: self.throttle.seconds_since(since),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::summary;
+    use std::time::UNIX_EPOCH;
+
+    fn sample(secs: u64, package_w: f64) -> Sample {
+        let timestamp = UNIX_EPOCH + Duration::from_secs(secs);
+        let mut sample = Sample::from_values(timestamp, &[0.0; FIELD_COUNT]);
+        sample.elapsed_ms = 1000.0;
+        sample.package_w = package_w;
+        sample
+    }
+
+    fn offset(sample: &Sample) -> u64 {
+        sample
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn keeps_whole_samples_back_to_the_longest_span() {
+        let base = Instant::now();
+        let mut history = History::new();
+        history.keep_samples(Duration::from_secs(3));
+        history.keep_samples(Duration::from_secs(2));
+        for secs in 1..=10 {
+            history.push(&sample(secs, secs as f64), base + Duration::from_secs(secs));
+        }
+
+        let latest = sample(10, 10.0);
+        let earlier = history.samples_before(&latest, Duration::from_secs(5));
+        let offsets: Vec<u64> = earlier.iter().map(offset).collect();
+        assert_eq!(offsets, vec![7, 8, 9]);
+        let earlier = history.samples_before(&latest, Duration::from_secs(1));
+        assert_eq!(earlier.iter().map(offset).collect::<Vec<_>>(), vec![9]);
+
+        // Every field is charted, not only the samples kept whole
+        let package = history.series(summary::field_index("package_w"));
+        let stats = package.stats(base + Duration::from_secs(10), Duration::from_secs(60));
+        assert_eq!(stats.mean, 5.5);
+        assert_eq!(stats.max, 10.0);
+    }
+
+    #[test]
+    fn summarises_a_window_like_the_samples_in_it() {
+        let base = Instant::now();
+        let mut history = History::new();
+        let samples: Vec<Sample> = (1..=20)
+            .map(|secs| sample(secs, if secs % 4 == 0 { 30.0 } else { 10.0 }))
+            .collect();
+        for sample in &samples {
+            history.push(sample, base + Duration::from_secs(offset(sample)));
+        }
+        let now = base + Duration::from_secs(20);
+
+        let rollup = history.summary(now, Duration::from_secs(60)).unwrap();
+        let expected = Summary::new(&samples);
+        let package = summary::field_index("package_w");
+        assert_eq!(rollup.samples, 20);
+        assert_eq!(rollup.seconds, expected.seconds);
+        assert_eq!(rollup.mean[package], expected.mean[package]);
+        assert_eq!(rollup.p50[package], expected.p50[package]);
+        assert_eq!(rollup.p95[package], expected.p95[package]);
+        assert_eq!(rollup.max[package], 30.0);
+        assert_eq!(rollup.energy.package, expected.energy.package);
+
+        // Points map back to the wall-clock times of their samples
+        let latest = history.latest().unwrap();
+        assert_eq!(offset(latest), 20);
+        assert_eq!(
+            history.timestamp(now - Duration::from_secs(5)),
+            samples[14].timestamp
+        );
+        assert!(history
+            .summary(now + Duration::from_secs(60), Duration::from_secs(10))
+            .is_none());
+    }
+
+    #[test]
+    fn counts_throttling_from_the_window_start() {
+        let base = Instant::now();
+        let mut history = History::new();
+        for secs in 1..=22 {
+            let mut sample = sample(secs, 10.0);
+            sample.p_cpu_active = 90.0;
+            sample.p_cpu_freq_mhz = if (5..=16).contains(&secs) {
+                2400.0
+            } else {
+                3200.0
+            };
+            history.push(&sample, base + Duration::from_secs(secs));
+        }
+        let now = base + Duration::from_secs(22);
+
+        let whole = history.summary(now, Duration::from_secs(60)).unwrap();
+        assert_eq!(whole.throttled_intervals, 1);
+        assert_eq!(whole.throttled_seconds, 12.0);
+        // Throttling that began before a window only counts from its start
+        let recent = history.summary(now, Duration::from_secs(10)).unwrap();
+        assert_eq!(recent.throttled_seconds, 4.0);
+        let after = history.summary(now, Duration::from_secs(5)).unwrap();
+        assert_eq!(after.throttled_intervals, 0);
+        assert!(after.throttled_seconds.is_sign_positive());
+        assert_eq!(
+            history.throttled(Domain::PCpu),
+            vec![(
+                base + Duration::from_secs(4),
+                base + Duration::from_secs(16)
+            )]
+        );
+    }
+
+    #[test]
+    fn clearing_keeps_the_span() {
+        let base = Instant::now();
+        let mut history = History::new();
+        history.keep_samples(Duration::from_secs(5));
+        history.push(&sample(1, 1.0), base);
+        history.clear();
+        assert!(history.latest().is_none());
+
+        for secs in 2..=4 {
+            history.push(&sample(secs, 1.0), base + Duration::from_secs(secs));
+        }
+        let earlier = history.samples_before(&sample(4, 1.0), Duration::MAX);
+        assert_eq!(earlier.iter().map(offset).collect::<Vec<_>>(), vec![2, 3]);
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+// A plain-HTTP endpoint split out of a URL like http://host:port/path?query; IPv6 hosts are
+// written in brackets, as in http://[::1]:8080/hook, and kept without them
+pub struct Url {
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+}
+
+impl Url {
+    pub fn parse(url: &str) -> io::Result<Self> {
+        let invalid = |message: &str| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", message, url))
+        };
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| invalid("Only http:// URLs are supported"))?;
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let port = |port: &str| port.parse().map_err(|_| invalid("Invalid port in URL"));
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(bracketed) => match bracketed.split_once(']') {
+                Some((host, "")) => (host, 80),
+                Some((host, rest)) => match rest.strip_prefix(':') {
+                    Some(rest) => (host, port(rest)?),
+                    None => return Err(invalid("Invalid port in URL")),
+                },
+                None => return Err(invalid("Unclosed [ in URL")),
+            },
+            None => match authority.rsplit_once(':') {
+                Some((host, rest)) => (host, port(rest)?),
+                None => (authority, 80),
+            },
+        };
+        if host.is_empty() {
+            return Err(invalid("Missing host in URL"));
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    // host:port as the Host header wants it, with IPv6 hosts back in brackets
+    fn authority(&self) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, self.port)
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+}
+
+// Sends a POST and fails unless the server answers with a 2xx status
+pub fn post(
+    url: &Url,
+    content_type: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    timeout: Duration,
+) -> io::Result<()> {
+    let addr = (url.host.as_str(), url.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Host has no addresses"))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        url.path,
+        url.authority(),
+        content_type,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes())?;
+    stream.write_all(body)?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "Unexpected response: {}",
+            status_line.trim()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_hosts_ports_and_paths() {
+        let url = Url::parse("http://localhost:8086/api/v2/write?org=lab").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("localhost", 8086));
+        assert_eq!(url.path, "/api/v2/write?org=lab");
+        let url = Url::parse("http://example.com").unwrap();
+        assert_eq!(
+            (url.host.as_str(), url.port, url.path.as_str()),
+            ("example.com", 80, "/")
+        );
+        assert!(Url::parse("https://example.com/").is_err());
+        assert!(Url::parse("http://:80/").is_err());
+    }
+
+    #[test]
+    fn parses_bracketed_ipv6_hosts() {
+        let url = Url::parse("http://[::1]:8080/hook").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("::1", 8080));
+        assert_eq!(url.path, "/hook");
+        assert_eq!(url.authority(), "[::1]:8080");
+        assert!((url.host.as_str(), url.port).to_socket_addrs().is_ok());
+
+        let url = Url::parse("http://[fe80::1]/").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("fe80::1", 80));
+        assert!(Url::parse("http://[::1:8080/hook").is_err());
+        assert!(Url::parse("http://[::1]8080/hook").is_err());
+    }
+}
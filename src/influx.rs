@@ -0,0 +1,281 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError, Sender};
+
+use crate::config::Influx;
+use crate::export::{self, Sink};
+use crate::http::{self, Url};
+use crate::marker::Marker;
+use crate::sample::Sample;
+
+// Unsent samples kept while the endpoint is unreachable; the oldest are dropped beyond this
+const MAX_PENDING: usize = 3600;
+const PUSH_TIMEOUT: Duration = Duration::from_secs(5);
+// How long exiting waits for the last batch to go out
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(6);
+
+pub struct InfluxSink {
+    tags: String,
+    output: Output,
+}
+
+enum Output {
+    File(BufWriter<File>),
+    // Pushing happens on its own thread so a slow endpoint can't stall the dashboard; `done`
+    // disconnects when the thread finishes, and `tx` is taken on drop to ask it to
+    Push {
+        tx: Option<Sender<String>>,
+        done: Receiver<()>,
+    },
+}
+
+enum Endpoint {
+    Udp(UdpSocket, SocketAddr),
+    Http(Url, Option<String>),
+}
+
+impl InfluxSink {
+    pub fn open(config: &Influx, target: &str, host: &str, chip: &str) -> io::Result<Self> {
+        let endpoint = if let Some(addr) = target.strip_prefix("udp://") {
+            let addr = addr
+                .to_socket_addrs()?
+                .next()
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Host has no addresses"))?;
+            Some(Endpoint::Udp(export::udp_socket_for(&addr)?, addr))
+        } else if target.starts_with("http://") {
+            Some(Endpoint::Http(Url::parse(target)?, config.token.clone()))
+        } else {
+            None
+        };
+
+        let output = match endpoint {
+            Some(endpoint) => {
+                let (tx, rx) = unbounded();
+                let (done_tx, done) = unbounded();
+                let batches = Batches {
+                    size: config.batch_size,
+                    max_age: Duration::from_millis(config.flush_interval_ms),
+                    retries: config.retries,
+                };
+                thread::spawn(move || {
+                    push(endpoint, rx, batches);
+                    drop(done_tx);
+                });
+                Output::Push { tx: Some(tx), done }
+            }
+            None => Output::File(BufWriter::new(File::create(target)?)),
+        };
+
+        Ok(Self {
+            tags: format!("host={},chip={}", escape_tag(host), escape_tag(chip)),
+            output,
+        })
+    }
+}
+
+impl InfluxSink {
+    fn send(&mut self, lines: String) -> io::Result<()> {
+        match &mut self.output {
+            Output::File(out) => {
+                out.write_all(lines.as_bytes())?;
+                out.flush()
+            }
+            Output::Push { tx, .. } => match tx {
+                Some(tx) if tx.send(lines).is_ok() => Ok(()),
+                _ => Err(io::Error::other("InfluxDB push thread stopped")),
+            },
+        }
+    }
+}
+
+impl Sink for InfluxSink {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let lines = format_lines(sample, &self.tags);
+        self.send(lines)
+    }
+
+    // Markers become points in their own measurement, for annotations in dashboards
+    fn mark(&mut self, marker: &Marker) -> io::Result<()> {
+        let timestamp = marker
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let name = marker.name.replace('\\', "\\\\").replace('"', "\\\"");
+        let line = format!("marker,{} name=\"{}\" {}\n", self.tags, name, timestamp);
+        self.send(line)
+    }
+}
+
+impl Drop for InfluxSink {
+    // Gives the push thread a moment to send what's left, rather than losing a partial batch
+    fn drop(&mut self) {
+        if let Output::Push { tx, done } = &mut self.output {
+            tx.take();
+            let _ = done.recv_timeout(SHUTDOWN_TIMEOUT);
+        }
+    }
+}
+
+struct Batches {
+    size: usize,
+    max_age: Duration,
+    retries: u32,
+}
+
+// Sends every `size` samples, or sooner once the oldest unsent one is `max_age` old, retrying
+// with backoff and keeping failed batches for the next attempt; what's left is sent once more
+// when the sink goes away
+fn push(endpoint: Endpoint, rx: Receiver<String>, batches: Batches) {
+    let mut pending: VecDeque<String> = VecDeque::new();
+    let mut oldest: Option<Instant> = None;
+    loop {
+        let wait = oldest.map_or(batches.max_age, |since| {
+            batches.max_age.saturating_sub(since.elapsed())
+        });
+        match rx.recv_timeout(wait) {
+            Ok(lines) => {
+                pending.push_back(lines);
+                oldest.get_or_insert_with(Instant::now);
+                if pending.len() < batches.size {
+                    continue;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) if pending.is_empty() => continue,
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                if !pending.is_empty() {
+                    let _ = endpoint.send(&pending);
+                }
+                return;
+            }
+        }
+        for attempt in 0..=batches.retries {
+            if endpoint.send(&pending).is_ok() {
+                pending.clear();
+                break;
+            }
+            if attempt < batches.retries {
+                thread::sleep(Duration::from_millis(500 << attempt.min(5)));
+            }
+        }
+        // A failed batch waits for the next one to fill, or for another `max_age`
+        oldest = if pending.is_empty() {
+            None
+        } else {
+            Some(Instant::now())
+        };
+        while pending.len() > MAX_PENDING {
+            pending.pop_front();
+        }
+    }
+}
+
+impl Endpoint {
+    fn send(&self, pending: &VecDeque<String>) -> io::Result<()> {
+        match self {
+            // One datagram per sample keeps each well under typical MTUs
+            Endpoint::Udp(socket, addr) => pending
+                .iter()
+                .try_for_each(|lines| socket.send_to(lines.as_bytes(), addr).map(|_| ())),
+            Endpoint::Http(url, token) => {
+                let body: String = pending.iter().map(String::as_str).collect();
+                let authorization = token.as_ref().map(|token| format!("Token {}", token));
+                let headers: Vec<(&str, &str)> = authorization
+                    .iter()
+                    .map(|value| ("Authorization", value.as_str()))
+                    .collect();
+                http::post(
+                    url,
+                    "text/plain; charset=utf-8",
+                    &headers,
+                    body.as_bytes(),
+                    PUSH_TIMEOUT,
+                )
+            }
+        }
+    }
+}
+
+fn format_lines(sample: &Sample, tags: &str) -> String {
+    let timestamp = sample
+        .timestamp
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let measurements = [
+        (
+            "cpu",
+            format!(
+                "e_active={},e_freq_mhz={},p_active={},p_freq_mhz={}",
+                sample.e_cpu_active,
+                sample.e_cpu_freq_mhz,
+                sample.p_cpu_active,
+                sample.p_cpu_freq_mhz
+            ),
+        ),
+        (
+            "gpu",
+            format!(
+                "active={},freq_mhz={}",
+                sample.gpu_active, sample.gpu_freq_mhz
+            ),
+        ),
+        ("ane", format!("w={}", sample.ane_w)),
+        (
+            "power",
+            format!(
+                "cpu_w={},gpu_w={},ane_w={},package_w={}",
+                sample.cpu_w, sample.gpu_w, sample.ane_w, sample.package_w
+            ),
+        ),
+        (
+            "memory",
+            format!(
+                "used_bytes={}i,total_bytes={}i,swap_used_bytes={}i,swap_total_bytes={}i",
+                sample.memory_used, sample.memory_total, sample.swap_used, sample.swap_total
+            ),
+        ),
+        (
+            "netdisk",
+            format!(
+                "net_in_bytes_per_sec={},net_out_bytes_per_sec={},\
+                net_in_packets_per_sec={},net_out_packets_per_sec={},\
+                disk_read_kbytes_per_sec={},disk_write_kbytes_per_sec={},\
+                disk_read_ops_per_sec={},disk_write_ops_per_sec={}",
+                sample.net_in_bytes_per_sec,
+                sample.net_out_bytes_per_sec,
+                sample.net_in_packets_per_sec,
+                sample.net_out_packets_per_sec,
+                sample.disk_read_kbytes_per_sec,
+                sample.disk_write_kbytes_per_sec,
+                sample.disk_read_ops_per_sec,
+                sample.disk_write_ops_per_sec
+            ),
+        ),
+        (
+            "thermal",
+            format!("pressure_level={}i", sample.thermal_pressure as i64),
+        ),
+    ];
+    measurements
+        .iter()
+        .map(|(name, fields)| format!("{},{} {} {}\n", name, tags, fields, timestamp))
+        .collect()
+}
+
+fn escape_tag(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -1,12 +1,38 @@
-
-use std::collections::VecDeque;
-use std::io::{self, BufRead, BufReader};
+mod alert;
+mod api;
+mod capture;
+mod check;
+mod cli;
+mod condition;
+mod config;
+mod diff;
+mod energy;
+mod export;
+mod history;
+mod http;
+mod influx;
+mod marker;
+mod notify;
+mod playback;
+mod prometheus;
+mod recording;
+mod run;
+mod sample;
+mod statsd;
+mod statusline;
+mod summary;
+mod throttle;
+mod timeseries;
+
+use std::io::{self, BufRead, BufReader, Write};
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
-use crossbeam_channel::{unbounded, Sender};
+use clap::Parser;
+use crossbeam_channel::{never, select, tick, unbounded, Receiver, Sender};
 use crossterm::event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode};
 use crossterm::execute;
 use crossterm::terminal::{
@@ -17,12 +43,35 @@ use regex::Regex;
 use tui::backend::CrosstermBackend;
 use tui::{
     layout::{Constraint, Direction, Layout, Rect},
-    style::Color,
+    style::{Color, Modifier, Style},
+    text::Span,
     widgets::{Block, Paragraph, Wrap},
-    widgets::canvas::{Canvas, Line},
+    widgets::canvas::{Canvas, Context, Line},
     Frame, Terminal,
 };
 
+use alert::{Alerts, Severity};
+use api::ApiServer;
+use capture::CaptureSink;
+use cli::Cli;
+use config::{Config, ConfigWatcher};
+use energy::{format_energy, EnergyMeter};
+use export::{CsvSink, JsonSink, Sink};
+use history::{History, SharedHistory};
+use influx::InfluxSink;
+use marker::{Marker, MarkerFifo};
+use notify::Notifier;
+use playback::Player;
+use prometheus::PrometheusSink;
+use recording::RecordingSink;
+use sample::Sample;
+use statsd::StatsdSink;
+use statusline::StatusLineSink;
+use summary::Summary;
+use throttle::Domain;
+use timeseries::{Point, Stats, TimeSeries};
+
+#[cfg(target_os = "macos")]
 use libc::{
     c_int, host_info64_t, host_statistics64, mach_host_self, mach_msg_type_number_t, natural_t,
     vm_statistics64_data_t, HOST_VM_INFO64,
@@ -38,12 +87,7 @@ struct CPUMetrics {
     cpu_w: f64,
     gpu_w: f64,
     package_w: f64,
-    e_cluster_active_history: VecDeque<(Instant, i32)>,
-    p_cluster_active_history: VecDeque<(Instant, i32)>,
-    ane_w_history: VecDeque<(Instant, f64)>,
-    cpu_w_history: VecDeque<(Instant, f64)>,
-    gpu_w_history: VecDeque<(Instant, f64)>,
-    package_w_history: VecDeque<(Instant, f64)>,
+    elapsed_ms: f64,
 }
 
 impl CPUMetrics {
@@ -57,73 +101,21 @@ impl CPUMetrics {
             cpu_w: 0.0,
             gpu_w: 0.0,
             package_w: 0.0,
-            e_cluster_active_history: VecDeque::new(),
-            p_cluster_active_history: VecDeque::new(),
-            ane_w_history: VecDeque::new(),
-            cpu_w_history: VecDeque::new(),
-            gpu_w_history: VecDeque::new(),
-            package_w_history: VecDeque::new(),
+            elapsed_ms: 0.0,
         }
     }
 
-    fn append_e_cluster_active(&mut self, value: i32) {
-        let now = Instant::now();
-        self.e_cluster_active_history.push_back((now, value));
-        retain_recent(&mut self.e_cluster_active_history);
-    }
-
-    fn append_p_cluster_active(&mut self, value: i32) {
-        let now = Instant::now();
-        self.p_cluster_active_history.push_back((now, value));
-        retain_recent(&mut self.p_cluster_active_history);
-    }
-
-    fn append_ane_w(&mut self, value: f64) {
-        let now = Instant::now();
-        self.ane_w_history.push_back((now, value));
-        retain_recent(&mut self.ane_w_history);
-    }
-
-    fn append_cpu_w(&mut self, value: f64) {
-        let now = Instant::now();
-        self.cpu_w_history.push_back((now, value));
-        retain_recent(&mut self.cpu_w_history);
-    }
-
-    fn append_gpu_w(&mut self, value: f64) {
-        let now = Instant::now();
-        self.gpu_w_history.push_back((now, value));
-        retain_recent(&mut self.gpu_w_history);
-    }
-
-    fn append_package_w(&mut self, value: f64) {
-        let now = Instant::now();
-        self.package_w_history.push_back((now, value));
-        retain_recent(&mut self.package_w_history);
-    }
-
-    fn average_e_cluster_active(&self) -> f64 {
-        average_history(&self.e_cluster_active_history)
-    }
-
-    fn average_p_cluster_active(&self) -> f64 {
-        average_history(&self.p_cluster_active_history)
-    }
-
-    fn average_ane_util(&self) -> f64 {
-        average_history(&self.ane_w_history)
-    }
-
-    fn average_cpu_w(&self) -> f64 {
-        average_history(&self.cpu_w_history)
-    }
-
-    fn average_gpu_w(&self) -> f64 {
-        average_history(&self.gpu_w_history)
-    }
-
-    fn average_package_w(&self) -> f64 {
-        average_history(&self.package_w_history)
+    // Takes the latest values from a sample
+    fn update(&mut self, sample: &Sample) {
+        self.e_cluster_active = sample.e_cpu_active as i32;
+        self.e_cluster_freq_mhz = sample.e_cpu_freq_mhz as i32;
+        self.p_cluster_active = sample.p_cpu_active as i32;
+        self.p_cluster_freq_mhz = sample.p_cpu_freq_mhz as i32;
+        self.ane_w = sample.ane_w;
+        self.cpu_w = sample.cpu_w;
+        self.gpu_w = sample.gpu_w;
+        self.package_w = sample.package_w;
+        self.elapsed_ms = sample.elapsed_ms;
     }
 }
 
@@ -152,13 +144,23 @@ impl NetDiskMetrics {
             write_kbytes_per_sec: 0.0,
         }
     }
+
+    fn update(&mut self, sample: &Sample) {
+        self.out_packets_per_sec = sample.net_out_packets_per_sec;
+        self.out_bytes_per_sec = sample.net_out_bytes_per_sec;
+        self.in_packets_per_sec = sample.net_in_packets_per_sec;
+        self.in_bytes_per_sec = sample.net_in_bytes_per_sec;
+        self.read_ops_per_sec = sample.disk_read_ops_per_sec;
+        self.write_ops_per_sec = sample.disk_write_ops_per_sec;
+        self.read_kbytes_per_sec = sample.disk_read_kbytes_per_sec;
+        self.write_kbytes_per_sec = sample.disk_write_kbytes_per_sec;
+    }
 }
 
 #[derive(Clone)]
 struct GPUMetrics {
     freq_mhz: i32,
     active: f64,
-    active_history: VecDeque<(Instant, f64)>,
 }
 
 impl GPUMetrics {
@@ -166,19 +168,22 @@ impl GPUMetrics {
         Self {
             freq_mhz: 0,
             active: 0.0,
-            active_history: VecDeque::new(),
         }
     }
 
-    fn append_active(&mut self, value: f64) {
-        let now = Instant::now();
-        self.active_history.push_back((now, value));
-        retain_recent(&mut self.active_history);
+    fn update(&mut self, sample: &Sample) {
+        self.freq_mhz = sample.gpu_freq_mhz as i32;
+        self.active = sample.gpu_active;
     }
+}
 
-    fn average_active(&self) -> f64 {
-        average_history(&self.active_history)
-    }
+// One complete powermetrics sample
+#[derive(Clone)]
+struct Reading {
+    cpu: CPUMetrics,
+    gpu: GPUMetrics,
+    netdisk: NetDiskMetrics,
+    thermal_pressure: f64,
 }
 
 struct MemoryMetrics {
@@ -187,27 +192,80 @@ struct MemoryMetrics {
     swap_total: u64,
     swap_used: u64,
     used_percent: f32,
-    used_percent_history: VecDeque<(Instant, f64)>,
 }
 
 impl MemoryMetrics {
-    fn new(previous: &Option<MemoryMetrics>) -> Self {
-        let mut metrics = get_memory_metrics();
-        if let Some(prev) = previous {
-            metrics.used_percent_history = prev.used_percent_history.clone();
+    fn new() -> Self {
+        Self {
+            total: 0,
+            used: 0,
+            swap_total: 0,
+            swap_used: 0,
+            used_percent: 0.0,
+        }
+    }
+
+    fn update(&mut self, sample: &Sample) {
+        self.total = sample.memory_total;
+        self.used = sample.memory_used;
+        self.swap_total = sample.swap_total;
+        self.swap_used = sample.swap_used;
+        self.used_percent = if self.total > 0 {
+            (self.used as f64 / self.total as f64 * 100.0) as f32
         } else {
-            metrics.used_percent_history = VecDeque::new();
+            0.0
+        };
+    }
+}
+
+// Everything the dashboard shows, fed one sample at a time from powermetrics or a recording
+struct Dashboard {
+    cpu: CPUMetrics,
+    gpu: GPUMetrics,
+    netdisk: NetDiskMetrics,
+    memory: MemoryMetrics,
+    energy: EnergyMeter,
+    thermal_pressure: f64,
+    markers: Vec<(Instant, String)>,
+    alerts: Alerts,
+    // Chart data, shared with the query API and triggered captures
+    history: SharedHistory,
+}
+
+impl Dashboard {
+    fn new(rules: &[config::AlertRule], history: SharedHistory) -> Self {
+        Self {
+            cpu: CPUMetrics::new(),
+            gpu: GPUMetrics::new(),
+            netdisk: NetDiskMetrics::new(),
+            memory: MemoryMetrics::new(),
+            energy: EnergyMeter::new(),
+            thermal_pressure: 0.0,
+            markers: Vec::new(),
+            alerts: Alerts::new(rules),
+            history,
         }
-        let now = Instant::now();
-        metrics
-            .used_percent_history
-            .push_back((now, metrics.used_percent as f64));
-        retain_recent(&mut metrics.used_percent_history);
-        metrics
     }
 
-    fn average_used_percent(&self) -> f64 {
-        average_history(&self.used_percent_history)
+    // Starts over with the same alert rules, e.g. before replaying a recording from the start
+    fn reset(&mut self) {
+        self.history.lock().unwrap().clear();
+        *self = Self::new(self.alerts.rules(), Arc::clone(&self.history));
+    }
+
+    fn apply(&mut self, sample: &Sample, time: Instant) -> Vec<alert::Event> {
+        self.cpu.update(sample);
+        self.gpu.update(sample);
+        self.netdisk.update(sample);
+        self.memory.update(sample);
+        self.energy.record(sample);
+        self.thermal_pressure = sample.thermal_pressure;
+        self.history.lock().unwrap().push(sample, time);
+        self.alerts.update(sample)
+    }
+
+    fn mark(&mut self, time: Instant, name: &str) {
+        self.markers.push((time, name.to_string()));
     }
 }
 
@@ -252,88 +310,401 @@ lazy_static! {
         Regex::new(r"GPU\s*(HW)?\s*active\s*residency:\s+(\d+\.\d+)%").unwrap();
     static ref GPU_FREQ_RE: Regex =
         Regex::new(r"GPU\s*(HW)?\s*active\s*frequency:\s+(\d+)\s+MHz").unwrap();
+    static ref ELAPSED_RE: Regex = Regex::new(r"\(([\d.]+)ms elapsed\)").unwrap();
     static ref SWAP_REGEX: Regex =
         Regex::new(r"total = (\d+\.\d+)([MG])\s+used = (\d+\.\d+)([MG])\s+free = (\d+\.\d+)([MG])").unwrap();
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    if unsafe { libc::geteuid() } != 0 {
+    let cli = Cli::parse();
+
+    let config_path = cli.config.clone().or_else(config::default_path);
+    let mut config = match &config_path {
+        Some(path) => config::load(path)
+            .map_err(|e| format!("Failed to load config {}: {}", path.display(), e))?,
+        None => Config::default(),
+    };
+    cli.apply(&mut config);
+    let config_watcher = config_path.map(ConfigWatcher::new);
+
+    if cli.needs_root() && unsafe { libc::geteuid() } != 0 {
         eprintln!("This tool requires root privileges. Please run it with sudo.");
         std::process::exit(1);
     }
 
+    if let Some(cli::Command::Check(args)) = &cli.command {
+        let code = check::check(args, config.interval_ms)?;
+        std::process::exit(code);
+    }
+
+    if let Some(cli::Command::Diff(args)) = &cli.command {
+        return diff::diff(args, &config.units, cli.json);
+    }
+
+    if let Some(cli::Command::Play(args)) = &cli.command {
+        let recording = recording::load(&args.file)
+            .map_err(|e| format!("Failed to read {}: {}", args.file.display(), e))?;
+        if recording.samples.is_empty() {
+            return Err(format!("{} has no samples", args.file.display()).into());
+        }
+        let info = recording.info.clone();
+        let feed = Feed::Playback(Player::new(recording));
+        return run_dashboard(&cli, config, config_watcher, feed, &info);
+    }
+
+    let model_info = get_apple_silicon_info();
+
+    if let Some(cli::Command::Run(args)) = &cli.command {
+        let code = run::run(args, &config, cli.json, &model_info)?;
+        std::process::exit(code);
+    }
+
+    // Marker names from the FIFO and the socket
+    let (marker_tx, markers) = unbounded();
+
+    // What the dashboard draws, the query API answers from and captures look back through
+    let history = History::shared();
+
+    let mut sinks: Vec<Box<dyn Sink>> = Vec::new();
+    if let Some(path) = &cli.csv {
+        let sink = CsvSink::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        sinks.push(Box::new(sink));
+    }
+    if let Some(path) = &cli.record {
+        let sink = RecordingSink::create(path, &model_info, config.interval_ms, SystemTime::now())
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        sinks.push(Box::new(sink));
+    }
+    if !cli.trigger.is_empty() {
+        let sink = CaptureSink::new(
+            &cli.trigger,
+            Duration::from_secs(cli.pre_trigger),
+            Duration::from_secs(cli.post_trigger),
+            cli.capture_dir.clone(),
+            &model_info,
+            config.interval_ms,
+            Arc::clone(&history),
+        )
+        .map_err(|e| format!("Failed to create {}: {}", cli.capture_dir.display(), e))?;
+        sinks.push(Box::new(sink));
+    }
+    let snapshot_samples = cli.snapshot_samples();
+    // A snapshot prints its own JSON summary rather than streaming samples
+    if cli.json && snapshot_samples.is_none() {
+        sinks.push(Box::new(JsonSink::new(&model_info, config.interval_ms)?));
+    }
+    if let Some(template) = &cli.statusline {
+        let sink = StatusLineSink::new(template, cli.statusline_format)?;
+        sinks.push(Box::new(sink));
+    }
+    if let Some(addr) = &cli.exporter {
+        let sink = PrometheusSink::serve(addr, &model_info)
+            .map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+        sinks.push(Box::new(sink));
+    }
+    let host = get_sysctl_string("kern.hostname").unwrap_or_else(|_| "unknown".to_string());
+    if let Some(target) = &config.influx.target {
+        let sink = InfluxSink::open(&config.influx, target, &host, &model_info.name)
+            .map_err(|e| format!("Failed to open {}: {}", target, e))?;
+        sinks.push(Box::new(sink));
+    }
+    if let Some(addr) = &config.statsd.addr {
+        let sink = StatsdSink::open(&config.statsd, addr)
+            .map_err(|e| format!("Failed to resolve {}: {}", addr, e))?;
+        sinks.push(Box::new(sink));
+    }
+
+    // Removes the socket when mtop exits
+    let _api = match &cli.socket {
+        Some(path) => Some(
+            ApiServer::serve(
+                path,
+                cli.socket_mode,
+                &model_info,
+                Arc::clone(&history),
+                marker_tx.clone(),
+            )
+            .map_err(|e| format!("Failed to listen on {}: {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+
+    // Removes the FIFO when mtop exits
+    let _fifo = match &cli.markers {
+        Some(path) => Some(
+            MarkerFifo::listen(path, marker_tx.clone())
+                .map_err(|e| format!("Failed to create FIFO {}: {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+    // Without any source the channel disconnects and the loops stop waiting on it
+    drop(marker_tx);
+
+    let notifiers = notify::open(&config.notify, &host, &model_info.name)
+        .map_err(|e| format!("Failed to set up alert notifications: {}", e))?;
+
+    let (tx, rx) = unbounded();
+    let running = spawn_collector(config.interval_ms, snapshot_samples, &tx);
+
+    // A status line with --once or --samples prints that many lines rather than a summary
+    if let (Some(count), None) = (snapshot_samples, &cli.statusline) {
+        // Let the channel disconnect if powermetrics exits early
+        drop(tx);
+        return run_snapshot(&cli, &config, &rx, count, &mut sinks, &history, &model_info);
+    }
+
+    let live = Live {
+        tx,
+        rx,
+        running,
+        sinks,
+        markers,
+        notifiers,
+        history,
+    };
+
+    // JSON and status lines go to stdout, so they can't share the terminal with the dashboard
+    if cli.headless || cli.json || cli.statusline.is_some() {
+        return run_headless(&cli, config, config_watcher, live);
+    }
+
+    run_dashboard(&cli, config, config_watcher, Feed::Live(live), &model_info)
+}
+
+// Samples from powermetrics and everywhere they go
+struct Live {
+    tx: Sender<Reading>,
+    rx: Receiver<Reading>,
+    running: Arc<Mutex<bool>>,
+    sinks: Vec<Box<dyn Sink>>,
+    markers: Receiver<String>,
+    notifiers: Vec<Box<dyn Notifier>>,
+    history: SharedHistory,
+}
+
+impl Live {
+    // Replaces powermetrics with one sampling at the new interval
+    fn restart(&mut self, interval_ms: u64) {
+        *self.running.lock().unwrap() = false;
+        self.running = spawn_collector(interval_ms, None, &self.tx);
+    }
+}
+
+// Where the dashboard's samples come from
+enum Feed {
+    Live(Live),
+    Playback(Player),
+}
+
+fn run_dashboard(
+    cli: &Cli,
+    mut config: Config,
+    mut config_watcher: Option<ConfigWatcher>,
+    mut feed: Feed,
+    model_info: &AppleSiliconInfo,
+) -> Result<(), Box<dyn std::error::Error>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    let (cpu_tx, cpu_rx) = unbounded();
-    let (gpu_tx, gpu_rx) = unbounded();
-    let (netdisk_tx, netdisk_rx) = unbounded();
-
-    let running = Arc::new(Mutex::new(true));
-    let running_clone = Arc::clone(&running);
-
-    thread::spawn(move || {
-        collect_metrics(cpu_tx, gpu_tx, netdisk_tx, running_clone);
-    });
+    let history = match &feed {
+        Feed::Live(live) => Arc::clone(&live.history),
+        Feed::Playback(_) => History::shared(),
+    };
+    let mut dashboard = Dashboard::new(&config.alerts, history);
+    let mut config_error: Option<String> = None;
+    let mut export_error: Option<String> = None;
+    let mut alert_error: Option<String> = None;
+    // The name being typed for a new marker, then the marker waiting to be added
+    let mut marker_input: Option<String> = None;
+    let mut typed_marker: Option<String> = None;
 
     let mut need_render = EventThrottler::new(Duration::from_millis(500));
+    let mut need_config_check = EventThrottler::new(Duration::from_secs(1));
 
-    let mut cpu_metrics = CPUMetrics::new();
-    let mut gpu_metrics = GPUMetrics::new();
-    let mut netdisk_metrics = NetDiskMetrics::new();
-    let mut memory_metrics = None;
-
-    let model_info = get_apple_silicon_info();
+    let mut view = View {
+        window: Duration::from_secs(config.history_secs),
+        average: AverageSpan::Window,
+        now: Instant::now(),
+    };
 
     // Main Event Loop
     loop {
+        let mut updated = false;
+
         if crossterm::event::poll(Duration::from_millis(100))? {
             if let Event::Key(key) = event::read()? {
-                if matches!(key.code, KeyCode::Char('q') | KeyCode::Char('Q')) {
-                    let mut running = running.lock().unwrap();
-                    *running = false;
-                    break;
+                if let Some(input) = &mut marker_input {
+                    match key.code {
+                        KeyCode::Char(c) => input.push(c),
+                        KeyCode::Backspace => {
+                            input.pop();
+                        }
+                        KeyCode::Enter => {
+                            typed_marker = Some(if input.trim().is_empty() {
+                                format!("Marker {}", dashboard.markers.len() + 1)
+                            } else {
+                                input.clone()
+                            });
+                            marker_input = None;
+                        }
+                        KeyCode::Esc => marker_input = None,
+                        _ => {}
+                    }
+                } else {
+                    match (key.code, &mut feed) {
+                        (KeyCode::Char('q') | KeyCode::Char('Q'), _) => break,
+                        (KeyCode::Char('w'), _) => view.window = cycle_window(view.window, true),
+                        (KeyCode::Char('W'), _) => view.window = cycle_window(view.window, false),
+                        (KeyCode::Char('a'), _) => view.average = view.average.next(),
+                        (KeyCode::Char('r'), _) => dashboard.energy.reset_mark(),
+                        (KeyCode::Char(' '), Feed::Playback(player)) => player.toggle_pause(),
+                        (KeyCode::Left, Feed::Playback(player)) => {
+                            player.seek(&mut dashboard, -10.0)
+                        }
+                        (KeyCode::Right, Feed::Playback(player)) => {
+                            player.seek(&mut dashboard, 10.0)
+                        }
+                        (KeyCode::PageUp, Feed::Playback(player)) => {
+                            player.seek(&mut dashboard, -60.0)
+                        }
+                        (KeyCode::PageDown, Feed::Playback(player)) => {
+                            player.seek(&mut dashboard, 60.0)
+                        }
+                        (KeyCode::Home, Feed::Playback(player)) => player.restart(&mut dashboard),
+                        (KeyCode::Char('+'), Feed::Playback(player)) => player.faster(),
+                        (KeyCode::Char('-'), Feed::Playback(player)) => player.slower(),
+                        (KeyCode::Char('m'), Feed::Live(_)) => marker_input = Some(String::new()),
+                        _ => {}
+                    }
                 }
+                updated = true;
             }
         }
 
-        let mut updated = false;
-
-        while let Ok(metrics) = cpu_rx.try_recv() {
-            cpu_metrics = metrics;
-            updated = true;
-        }
-
-        while let Ok(metrics) = gpu_rx.try_recv() {
-            gpu_metrics = metrics;
-            updated = true;
+        if need_config_check.should_notify() {
+            if let Some(result) = config_watcher.as_mut().and_then(|w| w.poll()) {
+                match result {
+                    Ok(mut new_config) => {
+                        cli.apply(&mut new_config);
+                        let restart = config.keep_startup_sections(&mut new_config);
+                        if let Feed::Live(live) = &mut feed {
+                            if new_config.interval_ms != config.interval_ms {
+                                live.restart(new_config.interval_ms);
+                            }
+                        }
+                        if new_config.history_secs != config.history_secs {
+                            view.window = Duration::from_secs(new_config.history_secs);
+                        }
+                        if new_config.alerts != config.alerts {
+                            dashboard.alerts.reload(&new_config.alerts);
+                        }
+                        config = new_config;
+                        config_error = (!restart.is_empty()).then(|| {
+                            let path = config_watcher.as_ref().unwrap().path().display();
+                            format!("{}: restart required for {}", path, restart.join(", "))
+                        });
+                    }
+                    Err(e) => {
+                        let path = config_watcher.as_ref().unwrap().path().display();
+                        config_error = Some(format!("{}: {}", path, e));
+                    }
+                }
+                updated = true;
+            }
         }
 
-        while let Ok(metrics) = netdisk_rx.try_recv() {
-            netdisk_metrics = metrics;
-            updated = true;
+        match &mut feed {
+            Feed::Live(Live {
+                rx,
+                sinks,
+                markers,
+                notifiers,
+                ..
+            }) => {
+                while let Ok(reading) = rx.try_recv() {
+                    let sample = Sample::new(&reading, &get_memory_metrics());
+                    let events = dashboard.apply(&sample, Instant::now());
+                    // Info alerts only highlight; the rest also ring the terminal bell
+                    if events
+                        .iter()
+                        .any(|event| event.firing && event.rule.severity > Severity::Info)
+                    {
+                        terminal.backend_mut().write_all(b"\x07")?;
+                        terminal.backend_mut().flush()?;
+                    }
+                    for event in &events {
+                        if let Some(e) = notify::notify_all(notifiers, event) {
+                            alert_error = Some(e.to_string());
+                        }
+                    }
+                    if let Some(e) = export::write_all(sinks, &sample) {
+                        export_error = Some(e.to_string());
+                    }
+                    updated = true;
+                }
+                for name in markers.try_iter().chain(typed_marker.take()) {
+                    let marker = Marker::now(&name);
+                    dashboard.mark(Instant::now(), &marker.name);
+                    if let Some(e) = export::mark_all(sinks, &marker) {
+                        export_error = Some(e.to_string());
+                    }
+                    updated = true;
+                }
+                view.now = Instant::now();
+            }
+            Feed::Playback(player) => {
+                updated |= player.advance(&mut dashboard);
+                view.now = player.now();
+            }
         }
 
         if updated || need_render.should_notify() {
-            let mem_metrics = MemoryMetrics::new(&memory_metrics);
-            memory_metrics = Some(mem_metrics);
-
-            terminal.draw(|f| {
-                draw_ui(
-                    f,
-                    &cpu_metrics,
-                    &gpu_metrics,
-                    &netdisk_metrics,
-                    &model_info,
-                    memory_metrics.as_ref().unwrap(),
-                )
-            })?;
+            let mut notes = Vec::new();
+            if let Feed::Live(Live {
+                sinks, notifiers, ..
+            }) = &feed
+            {
+                notes.extend(sinks.iter().filter_map(|sink| sink.status()));
+                notes.extend(notifiers.iter().filter_map(|notifier| notifier.status()));
+            }
+            if let Feed::Playback(player) = &feed {
+                let recording = player.recording();
+                notes.push(format!(
+                    "Recorded: {} on {}, every {} ms",
+                    sample::format_timestamp(recording.start),
+                    recording.os,
+                    recording.interval_ms
+                ));
+                notes.push(format!("Playback: {}", player.status()));
+            }
+            if let Some(input) = &marker_input {
+                notes.push(format!(
+                    "Marker name: {}_ (Enter to add, Esc to cancel)",
+                    input
+                ));
+            }
+            if let Some(error) = &config_error {
+                notes.push(format!("Config Error: {}", error));
+            }
+            if let Some(error) = &export_error {
+                notes.push(format!("Export Error: {}", error));
+            }
+            if let Some(error) = &alert_error {
+                notes.push(format!("Alert Error: {}", error));
+            }
+
+            terminal.draw(|f| draw_ui(f, &config, &view, &dashboard, model_info, &notes))?;
         }
     }
 
+    if let Feed::Live(live) = &feed {
+        *live.running.lock().unwrap() = false;
+    }
+
     disable_raw_mode()?;
     execute!(
         terminal.backend_mut(),
@@ -342,26 +713,318 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     )?;
     terminal.show_cursor()?;
 
+    println!("{}", dashboard.energy.summary(&config.cost));
+    if let Some(throttling) = dashboard.history.lock().unwrap().throttle.summary() {
+        println!("Likely thermal throttling: {}", throttling);
+    }
+
+    Ok(())
+}
+
+// Set by SIGINT or SIGTERM so a headless run stops cleanly, letting sinks flush
+static STOP: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_stop(_: libc::c_int) {
+    STOP.store(true, Ordering::SeqCst);
+}
+
+// Exports samples until powermetrics stops or the process is interrupted
+fn run_headless(
+    cli: &Cli,
+    mut config: Config,
+    mut config_watcher: Option<ConfigWatcher>,
+    mut live: Live,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut alerts = Alerts::new(&config.alerts);
+    let mut markers = live.markers.clone();
+    let config_check = tick(Duration::from_secs(1));
+    let handler = request_stop as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+    loop {
+        let error = select! {
+            recv(live.rx) -> reading => match reading {
+                Ok(reading) => {
+                    let sample = Sample::new(&reading, &get_memory_metrics());
+                    live.history.lock().unwrap().push(&sample, Instant::now());
+                    // stdout may be carrying JSON or status lines
+                    for event in alerts.update(&sample) {
+                        eprintln!("{} {}", sample::format_timestamp(event.timestamp), event);
+                        if let Some(e) = notify::notify_all(&mut live.notifiers, &event) {
+                            eprintln!("Failed to send alert: {}", e);
+                        }
+                    }
+                    export::write_all(&mut live.sinks, &sample)
+                }
+                Err(_) => break,
+            },
+            recv(markers) -> name => match name {
+                Ok(name) => export::mark_all(&mut live.sinks, &Marker::now(&name)),
+                Err(_) => {
+                    markers = never();
+                    None
+                }
+            },
+            recv(config_check) -> _ => {
+                // The collector clears its flag once powermetrics exits and its last sample is sent
+                if STOP.load(Ordering::SeqCst)
+                    || (!*live.running.lock().unwrap() && live.rx.is_empty())
+                {
+                    break;
+                }
+                if let Some(result) = config_watcher.as_mut().and_then(|w| w.poll()) {
+                    match result {
+                        Ok(mut new_config) => {
+                            cli.apply(&mut new_config);
+                            let restart = config.keep_startup_sections(&mut new_config);
+                            if !restart.is_empty() {
+                                let path = config_watcher.as_ref().unwrap().path().display();
+                                eprintln!(
+                                    "Config {}: restart required for {}",
+                                    path,
+                                    restart.join(", ")
+                                );
+                            }
+                            if new_config.interval_ms != config.interval_ms {
+                                live.restart(new_config.interval_ms);
+                            }
+                            if new_config.alerts != config.alerts {
+                                alerts.reload(&new_config.alerts);
+                            }
+                            config = new_config;
+                        }
+                        Err(e) => {
+                            let path = config_watcher.as_ref().unwrap().path().display();
+                            eprintln!("Failed to reload config {}: {}", path, e);
+                        }
+                    }
+                }
+                None
+            },
+        };
+        match error {
+            // The reader went away, e.g. `mtop --json | head`
+            Some(e) if e.kind() == io::ErrorKind::BrokenPipe => break,
+            Some(e) => return Err(format!("Failed to export: {}", e).into()),
+            None => {}
+        }
+    }
+    *live.running.lock().unwrap() = false;
+    Ok(())
+}
+
+// Collects a fixed number of samples and prints a summary of every panel
+fn run_snapshot(
+    cli: &Cli,
+    config: &Config,
+    rx: &Receiver<Reading>,
+    count: u32,
+    sinks: &mut Vec<Box<dyn Sink>>,
+    history: &SharedHistory,
+    model_info: &AppleSiliconInfo,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut samples = Vec::new();
+    for reading in rx.iter().take(count as usize) {
+        let sample = Sample::new(&reading, &get_memory_metrics());
+        history.lock().unwrap().push(&sample, Instant::now());
+        if let Some(e) = export::write_all(sinks, &sample) {
+            return Err(format!("Failed to export sample: {}", e).into());
+        }
+        samples.push(sample);
+    }
+    if samples.is_empty() {
+        return Err("powermetrics exited before producing a sample".into());
+    }
+
+    let summary = Summary::new(&samples);
+    if cli.json {
+        let mut record = summary::info_json(model_info);
+        record["interval_ms"] = serde_json::json!(config.interval_ms);
+        record["summary"] = summary.to_json();
+        println!("{}", record);
+    } else {
+        println!("{}", summary::format_info(model_info));
+        println!("{}", summary.format(&config.units));
+    }
     Ok(())
 }
 
+// The ANE chart treats 8 W as fully busy, since powermetrics doesn't report its residency
+const ANE_PERCENT_PER_W: f64 = 100.0 / 8.0;
+
+// Chart windows selectable at runtime with `w` / `W`
+const WINDOWS: [Duration; 6] = [
+    Duration::from_secs(30),
+    Duration::from_secs(120),
+    Duration::from_secs(600),
+    Duration::from_secs(3600),
+    Duration::from_secs(6 * 3600),
+    timeseries::MAX_RETENTION,
+];
+
+// Span the chart labels summarise, cycled with `a`
+#[derive(Clone, Copy)]
+enum AverageSpan {
+    Window,
+    Last(Duration),
+    Session,
+}
+
+impl AverageSpan {
+    fn next(self) -> Self {
+        match self {
+            AverageSpan::Window => AverageSpan::Last(Duration::from_secs(10)),
+            AverageSpan::Last(span) if span < Duration::from_secs(60) => {
+                AverageSpan::Last(Duration::from_secs(60))
+            }
+            AverageSpan::Last(_) => AverageSpan::Session,
+            AverageSpan::Session => AverageSpan::Window,
+        }
+    }
+}
+
+struct View {
+    window: Duration,
+    average: AverageSpan,
+    // Live this is the current time; during playback it follows the recording
+    now: Instant,
+}
+
+impl View {
+    fn stats<T>(&self, series: &TimeSeries<T>) -> Stats
+    where
+        T: Into<f64> + Copy,
+    {
+        match self.average {
+            AverageSpan::Window => series.stats(self.now, self.window),
+            AverageSpan::Last(span) => series.stats(self.now, span),
+            AverageSpan::Session => series.session_stats(self.now),
+        }
+    }
+
+    fn average_label(&self) -> String {
+        match self.average {
+            AverageSpan::Window => format_window(self.window),
+            AverageSpan::Last(span) => format_window(span),
+            AverageSpan::Session => "session".to_string(),
+        }
+    }
+}
+
+fn cycle_window(current: Duration, forward: bool) -> Duration {
+    if forward {
+        WINDOWS
+            .iter()
+            .find(|&&w| w > current)
+            .copied()
+            .unwrap_or(WINDOWS[0])
+    } else {
+        WINDOWS
+            .iter()
+            .rev()
+            .find(|&&w| w < current)
+            .copied()
+            .unwrap_or(WINDOWS[WINDOWS.len() - 1])
+    }
+}
+
+fn format_window(window: Duration) -> String {
+    let secs = window.as_secs();
+    if secs >= 3600 && secs.is_multiple_of(3600) {
+        format!("{}h", secs / 3600)
+    } else if secs >= 60 && secs.is_multiple_of(60) {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+// `samples` limits powermetrics to that many samples instead of running until stopped
+fn spawn_collector(
+    interval_ms: u64,
+    samples: Option<u32>,
+    tx: &Sender<Reading>,
+) -> Arc<Mutex<bool>> {
+    let running = Arc::new(Mutex::new(true));
+    let running_clone = Arc::clone(&running);
+
+    let tx = tx.clone();
+
+    thread::spawn(move || {
+        collect_metrics(interval_ms, samples, tx, Arc::clone(&running_clone));
+        // Lets a headless run, which keeps a sender for restarts, see that powermetrics is gone
+        *running_clone.lock().unwrap() = false;
+    });
+
+    running
+}
+
+// `notes` are extra lines for the info panel, such as errors and playback status
 fn draw_ui(
     f: &mut Frame<CrosstermBackend<std::io::Stdout>>,
-    cpu_metrics: &CPUMetrics,
-    gpu_metrics: &GPUMetrics,
-    netdisk_metrics: &NetDiskMetrics,
+    config: &Config,
+    view: &View,
+    dashboard: &Dashboard,
     model_info: &AppleSiliconInfo,
-    memory_metrics: &MemoryMetrics,
+    notes: &[String],
 ) {
-    let size = f.size();
+    let mut size = f.size();
+    let alerts = &dashboard.alerts;
+    let firing = alerts.firing();
+    if let Some((worst, ..)) = firing.first() {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+            .split(size);
+        size = chunks[0];
+        let background = match worst.severity {
+            Severity::Critical => Color::Red,
+            Severity::Warning => Color::Yellow,
+            Severity::Info => Color::Blue,
+        };
+        let now = SystemTime::now();
+        let text: Vec<String> = firing
+            .iter()
+            .map(|(rule, value, since)| {
+                format!(
+                    "{}: {} ({}) for {}",
+                    rule.severity,
+                    rule.rule,
+                    alert::format_value(rule.rule.condition.field(), *value),
+                    energy::format_elapsed(now.duration_since(*since).unwrap_or_default())
+                )
+            })
+            .collect();
+        let status = Paragraph::new(format!(" ALERT  {}", text.join("  |  "))).style(
+            Style::default()
+                .fg(Color::Black)
+                .bg(background)
+                .add_modifier(Modifier::BOLD),
+        );
+        f.render_widget(status, chunks[1]);
+    }
+    let cpu_metrics = &dashboard.cpu;
+    let gpu_metrics = &dashboard.gpu;
+    let netdisk_metrics = &dashboard.netdisk;
+    let memory_metrics = &dashboard.memory;
+    let energy_meter = &dashboard.energy;
+    let history = dashboard.history.lock().unwrap();
+    let series = |field: &str| history.series(summary::field_index(field));
+    let average_label = view.average_label();
+    let layout = &config.layout;
+    let colors = &config.colors;
+    let units = &config.units;
+    let thresholds = &config.thresholds;
 
     // Split the screen vertically into top and bottom halves
     let vertical_chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
             [
-                Constraint::Percentage(50), // Top Half
-                Constraint::Percentage(50), // Bottom Half
+                Constraint::Percentage(layout.top),       // Top Half
+                Constraint::Percentage(100 - layout.top), // Bottom Half
             ]
             .as_ref(),
         )
@@ -384,8 +1047,8 @@ fn draw_ui(
         .direction(Direction::Vertical)
         .constraints(
             [
-                Constraint::Percentage(80), // CPU Utilization
-                Constraint::Percentage(20), // CPU Power
+                Constraint::Percentage(layout.utilization), // CPU Utilization
+                Constraint::Percentage(100 - layout.utilization), // CPU Power
             ]
             .as_ref(),
         )
@@ -403,40 +1066,75 @@ fn draw_ui(
         )
         .split(left_split[0]);
 
-    let e_cpu_avg = cpu_metrics.average_e_cluster_active();
+    let e_cpu_stats = view.stats(series("e_cpu_active_percent"));
     render_utilization_chart(
         f,
         cpu_utilization_chunks[0],
-        "\n E-CPU Usage",
-        &format!(
-            "{}% @ {}MHz\n \n \n Avg: {:.1}% \n",
-            cpu_metrics.e_cluster_active, cpu_metrics.e_cluster_freq_mhz, e_cpu_avg
+        chart_block(
+            "\n E-CPU Usage",
+            &format!(
+                "{}% @ {}MHz\n \n \n {} \n",
+                cpu_metrics.e_cluster_active,
+                cpu_metrics.e_cluster_freq_mhz,
+                stats_label(&e_cpu_stats, &average_label, format_percent),
+            ),
+            config::exceeds(thresholds.e_cpu, cpu_metrics.e_cluster_active as f64)
+                || alerts.on(&["e_cpu_active_percent", "e_cpu_freq_mhz"]),
         ),
-        &cpu_metrics.e_cluster_active_history,
-        Color::Green,
+        series("e_cpu_active_percent"),
+        1.0,
+        view,
+        &dashboard.markers,
+        &[],
+        colors.e_cpu,
     );
 
-    let p_cpu_avg = cpu_metrics.average_p_cluster_active();
+    let p_cpu_stats = view.stats(series("p_cpu_active_percent"));
     render_utilization_chart(
         f,
         cpu_utilization_chunks[1],
-        "\n P-CPU Usage",
-        &format!(
-            "{}% @ {}MHz\n \n \n Avg: {:.1}% \n",
-            cpu_metrics.p_cluster_active, cpu_metrics.p_cluster_freq_mhz, p_cpu_avg
+        chart_block(
+            "\n P-CPU Usage",
+            &format!(
+                "{}% @ {}MHz{}\n \n \n {} \n",
+                cpu_metrics.p_cluster_active,
+                cpu_metrics.p_cluster_freq_mhz,
+                throttle_note(&history, Domain::PCpu),
+                stats_label(&p_cpu_stats, &average_label, format_percent),
+            ),
+            config::exceeds(thresholds.p_cpu, cpu_metrics.p_cluster_active as f64)
+                || alerts.on(&["p_cpu_active_percent", "p_cpu_freq_mhz"]),
         ),
-        &cpu_metrics.p_cluster_active_history,
-        Color::Yellow,
+        series("p_cpu_active_percent"),
+        1.0,
+        view,
+        &dashboard.markers,
+        &history.throttled(Domain::PCpu),
+        colors.p_cpu,
     );
 
     // CPU Power
+    let cpu_w_stats = view.stats(series("cpu_w"));
     render_power_chart(
         f,
         left_split[1],
-        "\n CPU Power",
-        &format!("{:.2} W \n", cpu_metrics.cpu_w),
-        &cpu_metrics.cpu_w_history,
-        Color::Red,
+        chart_block(
+            "\n CPU Power",
+            &format!(
+                "{}  {} \n",
+                units.format_power(cpu_metrics.cpu_w),
+                stats_label(&cpu_w_stats, &average_label, |w| units.format_power(w)),
+            ),
+            config::exceeds(thresholds.cpu_w, cpu_metrics.cpu_w) || alerts.on(&["cpu_w"]),
+        ),
+        series("cpu_w"),
+        view,
+        &dashboard.markers,
+        colors.cpu_power,
+        &[energy_label(
+            energy_meter.total().cpu,
+            energy_meter.since_mark().cpu,
+        )],
     );
 
     // Right Column: GPU & ANE Utilization and GPU Power
@@ -444,8 +1142,8 @@ fn draw_ui(
         .direction(Direction::Vertical)
         .constraints(
             [
-                Constraint::Percentage(80), // GPU & ANE Utilization
-                Constraint::Percentage(20), // GPU Power
+                Constraint::Percentage(layout.utilization), // GPU & ANE Utilization
+                Constraint::Percentage(100 - layout.utilization), // GPU Power
             ]
             .as_ref(),
         )
@@ -463,40 +1161,74 @@ fn draw_ui(
         )
         .split(right_split[0]);
 
-    let ane_util = (cpu_metrics.ane_w * 100.0 / 8.0).clamp(0.0, 100.0);
-    let ane_avg = cpu_metrics.average_ane_util();
+    let ane_util = (cpu_metrics.ane_w * ANE_PERCENT_PER_W).clamp(0.0, 100.0);
+    let ane_stats = view.stats(series("ane_w")).scaled(ANE_PERCENT_PER_W);
     render_utilization_chart(
         f,
         gpu_ane_utilization_chunks[0],
-        "\n ANE Usage",
-        &format!(
-            "{:.0}% @ {:.2}W\n \n \n Avg: {:.1}% \n",
-            ane_util, cpu_metrics.ane_w, ane_avg
+        chart_block(
+            "\n ANE Usage",
+            &format!(
+                "{:.0}% @ {}\n \n \n {} \n",
+                ane_util,
+                units.format_power(cpu_metrics.ane_w),
+                stats_label(&ane_stats, &average_label, format_percent),
+            ),
+            config::exceeds(thresholds.ane, ane_util) || alerts.on(&["ane_w"]),
         ),
-        &cpu_metrics.ane_w_history,
-        Color::Blue,
+        series("ane_w"),
+        ANE_PERCENT_PER_W,
+        view,
+        &dashboard.markers,
+        &[],
+        colors.ane,
     );
 
-    let gpu_avg = gpu_metrics.average_active();
+    let gpu_stats = view.stats(series("gpu_active_percent"));
     render_utilization_chart(
         f,
         gpu_ane_utilization_chunks[1],
-        "\n GPU Usage",
-        &format!(
-            "{:.0}% @ {}MHz\n \n \n Avg: {:.1}% \n",
-            gpu_metrics.active, gpu_metrics.freq_mhz, gpu_avg
+        chart_block(
+            "\n GPU Usage",
+            &format!(
+                "{:.0}% @ {}MHz{}\n \n \n {} \n",
+                gpu_metrics.active,
+                gpu_metrics.freq_mhz,
+                throttle_note(&history, Domain::Gpu),
+                stats_label(&gpu_stats, &average_label, format_percent),
+            ),
+            config::exceeds(thresholds.gpu, gpu_metrics.active)
+                || alerts.on(&["gpu_active_percent", "gpu_freq_mhz"]),
         ),
-        &gpu_metrics.active_history,
-        Color::Magenta,
+        series("gpu_active_percent"),
+        1.0,
+        view,
+        &dashboard.markers,
+        &history.throttled(Domain::Gpu),
+        colors.gpu,
     );
     // GPU Power
+    let gpu_w_stats = view.stats(series("gpu_w"));
     render_power_chart(
         f,
         right_split[1],
-        "\n GPU Power",
-        &format!("{:.2} W \n", cpu_metrics.gpu_w),
-        &cpu_metrics.gpu_w_history,
-        Color::Red,
+        chart_block(
+            "\n GPU Power",
+            &format!(
+                "{}  {} \n",
+                units.format_power(cpu_metrics.gpu_w),
+                stats_label(&gpu_w_stats, &average_label, |w| units.format_power(w)),
+            ),
+            config::exceeds(thresholds.gpu_w, cpu_metrics.gpu_w) || alerts.on(&["gpu_w"]),
+        ),
+        series("gpu_w"),
+        view,
+        &dashboard.markers,
+        colors.gpu_power,
+        &[energy_label(
+            energy_meter.total().gpu,
+            energy_meter.since_mark().gpu,
+        )],
     );
 
     // --- Bottom Half ---
@@ -504,30 +1236,51 @@ fn draw_ui(
         .direction(Direction::Vertical)
         .constraints(
             [
-                Constraint::Percentage(50), // Memory Usage
-                Constraint::Percentage(50), // Apple Silicon Info, Network & Disk Info, Package Power
+                Constraint::Percentage(layout.memory),       // Memory Usage
+                Constraint::Percentage(100 - layout.memory), // Apple Silicon Info, Network & Disk Info, Package Power
             ]
             .as_ref(),
         )
         .split(vertical_chunks[1]);
 
     // Memory Usage spanning the top half of the bottom half
-    let mem_avg = memory_metrics.average_used_percent();
+    // Total memory doesn't change, so used bytes scale straight to a percentage
+    let mem_percent_per_byte = if memory_metrics.total > 0 {
+        100.0 / memory_metrics.total as f64
+    } else {
+        0.0
+    };
+    let mem_stats = view
+        .stats(series("memory_used_bytes"))
+        .scaled(mem_percent_per_byte);
     render_utilization_chart(
         f,
         bottom_split[0],
-        "\n Memory Usage",
-        &format!(
-            "{:.1}%\n \n \n {:.2} GB / {:.2} GB\n \n \n Swap Used: {:.2} GB / {:.2} GB\n \n \n Avg: {:.1}% \n",
-            memory_metrics.used_percent,
-            (memory_metrics.used) as f64 / 1024.0 / 1024.0 / 1024.0,
-            (memory_metrics.total) as f64 / 1024.0 / 1024.0 / 1024.0,
-            (memory_metrics.swap_used) as f64 / 1024.0 / 1024.0 / 1024.0,
-            (memory_metrics.swap_total) as f64 / 1024.0 / 1024.0 / 1024.0,
-            mem_avg,
+        chart_block(
+            "\n Memory Usage",
+            &format!(
+                "{:.1}%\n \n \n {} / {}\n \n \n Swap Used: {} / {}\n \n \n {} \n",
+                memory_metrics.used_percent,
+                units.format_memory(memory_metrics.used),
+                units.format_memory(memory_metrics.total),
+                units.format_memory(memory_metrics.swap_used),
+                units.format_memory(memory_metrics.swap_total),
+                stats_label(&mem_stats, &average_label, format_percent),
+            ),
+            config::exceeds(thresholds.memory, memory_metrics.used_percent as f64)
+                || alerts.on(&[
+                    "memory_used_bytes",
+                    "memory_total_bytes",
+                    "swap_used_bytes",
+                    "swap_total_bytes",
+                ]),
         ),
-        &memory_metrics.used_percent_history,
-        Color::Cyan,
+        series("memory_used_bytes"),
+        mem_percent_per_byte,
+        view,
+        &dashboard.markers,
+        &[],
+        colors.memory,
     );
 
     // Bottom part of the bottom half: Apple Silicon Info, Network & Disk Info, Package Power
@@ -544,18 +1297,28 @@ fn draw_ui(
         .split(bottom_split[1]);
 
     // Apple Silicon Info
-    let model_text = format!(
-        "Model: {}\nE-Cores: {}\nP-Cores: {}\nGPU Cores: {}",
+    let mut model_text = format!(
+        "Model: {}\nE-Cores: {}\nP-Cores: {}\nGPU Cores: {}\nThermal Pressure: {}",
         model_info.name,
         model_info.e_core_count,
         model_info.p_core_count,
         model_info.gpu_core_count,
+        sample::thermal_pressure_name(dashboard.thermal_pressure),
     );
+    for note in notes {
+        model_text.push('\n');
+        model_text.push_str(note);
+    }
     let model_paragraph = Paragraph::new(model_text)
         .block(
             Block::default()
                 .title("\n Apple Silicon Info \n")
-                .borders(tui::widgets::Borders::ALL),
+                .borders(tui::widgets::Borders::ALL)
+                .border_style(if alerts.on(&["thermal_pressure_level"]) {
+                    Style::default().fg(Color::Red)
+                } else {
+                    Style::default()
+                }),
         )
         .wrap(Wrap { trim: true });
     f.render_widget(model_paragraph, lower_bottom_chunks[0]);
@@ -585,174 +1348,302 @@ fn draw_ui(
     f.render_widget(netdisk_paragraph, lower_bottom_chunks[1]);
 
     // Package Power
+    let package_w_stats = view.stats(series("package_w"));
     render_power_chart(
         f,
         lower_bottom_chunks[2],
-        "\n Package Power",
-        &format!("{:.2} W \n", cpu_metrics.package_w),
-        &cpu_metrics.package_w_history,
-        Color::Red,
+        chart_block(
+            "\n Package Power",
+            &format!(
+                "{}  {} \n",
+                units.format_power(cpu_metrics.package_w),
+                stats_label(&package_w_stats, &average_label, |w| units.format_power(w)),
+            ),
+            config::exceeds(thresholds.package_w, cpu_metrics.package_w)
+                || alerts.on(&["package_w"]),
+        ),
+        series("package_w"),
+        view,
+        &dashboard.markers,
+        colors.package_power,
+        &[
+            energy_label(
+                energy_meter.total().package,
+                energy_meter.since_mark().package,
+            ),
+            energy_meter
+                .cost_label(&config.cost, package_w_stats.mean)
+                .unwrap_or_default(),
+        ],
     );
 }
 
-fn render_utilization_chart<T>(
+// `scale` turns the history's values into percentages
+#[allow(clippy::too_many_arguments)]
+fn render_utilization_chart(
     f: &mut Frame<CrosstermBackend<std::io::Stdout>>,
     area: Rect,
-    title: &str,
-    label: &str,
-    history: &VecDeque<(Instant, T)>,
+    block: Block,
+    history: &TimeSeries<f64>,
+    scale: f64,
+    view: &View,
+    markers: &[(Instant, String)],
+    throttled: &[(Instant, Instant)],
     color: Color,
-) where
-    T: Into<f64> + Copy,
-{
-    let now = Instant::now();
-    let data: Vec<(f64, f64)> = history
+) {
+    let data: Vec<ChartPoint> = history
+        .points(view.now, view.window)
         .iter()
-        .map(|(time, value)| {
-            let elapsed = now.duration_since(*time).as_secs_f64();
-            (-elapsed, (*value).into())
-        })
+        .map(|point| ChartPoint::new(view.now, point, scale))
         .collect();
 
-    let x_bounds = [-120.0, 0.0];
+    let x_bounds = [-view.window.as_secs_f64(), 0.0];
     let y_bounds = [0.0, 100.0];
+    let markers = marker_lines(view, markers);
+    let throttled = throttle_spans(view, throttled);
 
     let canvas = Canvas::default()
-        .block(
-            Block::default()
-                .title(format!("{}: {}", title, label))
-                .borders(tui::widgets::Borders::ALL),
-        )
+        .block(block)
         .x_bounds(x_bounds)
         .y_bounds(y_bounds)
         .paint(move |ctx| {
-            for &(x, y) in &data {
-                ctx.draw(&Line {
-                    x1: x,
-                    y1: 0.0,
-                    x2: x,
-                    y2: y,
-                    color,
-                });
-            }
-
-            for window in data.windows(2) {
-                if let [start, end] = window {
-                    ctx.draw(&Line {
-                        x1: start.0,
-                        y1: start.1,
-                        x2: end.0,
-                        y2: end.1,
-                        color: Color::White,
-                    });
-                }
-            }
+            paint_chart(ctx, &data, color);
+            paint_throttling(ctx, &throttled, y_bounds[1]);
+            paint_markers(ctx, &markers, y_bounds[1]);
         });
 
     f.render_widget(canvas, area);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_power_chart(
     f: &mut Frame<CrosstermBackend<std::io::Stdout>>,
     area: Rect,
-    title: &str,
-    label: &str,
-    history: &VecDeque<(Instant, f64)>,
+    block: Block,
+    history: &TimeSeries<f64>,
+    view: &View,
+    markers: &[(Instant, String)],
     color: Color,
+    overlay: &[String],
 ) {
-    let now = Instant::now();
-    // Find the peak in the last 120 seconds
-    let peak = history
-        .iter()
-        .filter(|(time, _)| *time >= now - Duration::from_secs(120))
-        .map(|(_, value)| value)
-        .fold(0.0f64, |a, &b| a.max(b));
+    // Find the peak within the window
+    let peak = history.stats(view.now, view.window).max;
     if peak == 0.0 {
         return;
     }
     // Collect data as proportion of peak
-    let data: Vec<(f64, f64)> = history
+    let data: Vec<ChartPoint> = history
+        .points(view.now, view.window)
         .iter()
-        .map(|(time, value)| {
-            let elapsed = now.duration_since(*time).as_secs_f64();
-            (-elapsed, (value / peak) * 100.0)
-        })
+        .map(|point| ChartPoint::new(view.now, point, 100.0 / peak))
         .collect();
 
-    let x_bounds = [-120.0, 0.0];
+    let x_bounds = [-view.window.as_secs_f64(), 0.0];
     let y_bounds = [0.0, 100.0];
+    // Overlay lines are stacked one terminal row apart inside the borders
+    let row_height = y_bounds[1] / area.height.saturating_sub(2).max(1) as f64;
+    let markers = marker_lines(view, markers);
 
     let canvas = Canvas::default()
-        .block(
-            Block::default()
-                .title(format!("{}: {}", title, label))
-                .borders(tui::widgets::Borders::ALL),
-        )
+        .block(block)
         .x_bounds(x_bounds)
         .y_bounds(y_bounds)
         .paint(move |ctx| {
-            for &(x, y) in &data {
-                ctx.draw(&Line {
-                    x1: x,
-                    y1: 0.0,
-                    x2: x,
-                    y2: y,
-                    color,
-                });
-            }
-
-            for window in data.windows(2) {
-                if let [start, end] = window {
-                    ctx.draw(&Line {
-                        x1: start.0,
-                        y1: start.1,
-                        x2: end.0,
-                        y2: end.1,
-                        color: Color::White,
-                    });
-                }
+            paint_chart(ctx, &data, color);
+            paint_markers(ctx, &markers, y_bounds[1]);
+            for (i, line) in overlay.iter().enumerate() {
+                ctx.print(
+                    x_bounds[0],
+                    y_bounds[1] - i as f64 * row_height,
+                    line.clone(),
+                );
             }
         });
 
     f.render_widget(canvas, area);
 }
 
-fn retain_recent<T>(history: &mut VecDeque<(Instant, T)>) {
-    let cutoff = Instant::now() - Duration::from_secs(120);
-    while let Some(&(time, _)) = history.front() {
-        if time < cutoff {
-            history.pop_front();
-        } else {
-            break;
+fn throttle_note(history: &History, domain: Domain) -> &'static str {
+    if history.throttle.active(domain) {
+        " (likely throttled)"
+    } else {
+        ""
+    }
+}
+
+fn energy_label(total: f64, since_mark: f64) -> String {
+    format!(
+        "Energy: {}  Mark: {}",
+        format_energy(total),
+        format_energy(since_mark)
+    )
+}
+
+fn stats_label(stats: &Stats, span_label: &str, format: impl Fn(f64) -> String) -> String {
+    format!(
+        "Avg ({}): {}  P50: {}  P95: {}  Max: {}",
+        span_label,
+        format(stats.mean),
+        format(stats.p50),
+        format(stats.p95),
+        format(stats.max)
+    )
+}
+
+fn format_percent(value: f64) -> String {
+    format!("{:.1}%", value)
+}
+
+// A history point positioned relative to now, scaled for the chart's y axis
+struct ChartPoint {
+    x: f64,
+    min: f64,
+    avg: f64,
+    max: f64,
+}
+
+impl ChartPoint {
+    fn new(now: Instant, point: &Point, scale: f64) -> Self {
+        Self {
+            x: -now.duration_since(point.time).as_secs_f64(),
+            min: point.min * scale,
+            avg: point.avg * scale,
+            max: point.max * scale,
+        }
+    }
+}
+
+fn paint_chart(ctx: &mut Context, data: &[ChartPoint], color: Color) {
+    // Bars reach each point's maximum
+    for point in data {
+        ctx.draw(&Line {
+            x1: point.x,
+            y1: 0.0,
+            x2: point.x,
+            y2: point.max,
+            color,
+        });
+    }
+
+    // Rolled-up points also trace their minimum beneath the average line
+    paint_trace(ctx, data, Color::DarkGray, |point| point.min);
+    paint_trace(ctx, data, Color::White, |point| point.avg);
+}
+
+// Markers inside the view's window, positioned like chart points
+fn marker_lines(view: &View, markers: &[(Instant, String)]) -> Vec<(f64, String)> {
+    markers
+        .iter()
+        .filter(|(time, _)| view.now.saturating_duration_since(*time) <= view.window)
+        .map(|(time, name)| {
+            (
+                -view.now.saturating_duration_since(*time).as_secs_f64(),
+                name.clone(),
+            )
+        })
+        .collect()
+}
+
+// Throttling intervals overlapping the view's window, clipped to it
+fn throttle_spans(view: &View, spans: &[(Instant, Instant)]) -> Vec<(f64, f64)> {
+    let x = |time: Instant| -view.now.saturating_duration_since(time).as_secs_f64();
+    let left = -view.window.as_secs_f64();
+    spans
+        .iter()
+        .filter(|(_, end)| x(*end) >= left)
+        .map(|(start, end)| (x(*start).max(left), x(*end)))
+        .collect()
+}
+
+// A red bar along the top of each interval, labelled where it starts
+fn paint_throttling(ctx: &mut Context, spans: &[(f64, f64)], top: f64) {
+    for (start, end) in spans {
+        for y in [top, top * 0.97] {
+            ctx.draw(&Line {
+                x1: *start,
+                y1: y,
+                x2: *end,
+                y2: y,
+                color: Color::Red,
+            });
         }
+        ctx.print(
+            *start,
+            top * 0.9,
+            Span::styled("throttled", Style::default().fg(Color::Red)),
+        );
     }
 }
 
-fn average_history<T>(history: &VecDeque<(Instant, T)>) -> f64
-where
-    T: Into<f64> + Copy,
-{
-    if history.is_empty() {
-        return 0.0;
+// A vertical line per marker with its name at the top
+fn paint_markers(ctx: &mut Context, markers: &[(f64, String)], top: f64) {
+    for (x, name) in markers {
+        ctx.draw(&Line {
+            x1: *x,
+            y1: 0.0,
+            x2: *x,
+            y2: top,
+            color: Color::Yellow,
+        });
+        ctx.print(
+            *x,
+            top,
+            Span::styled(name.clone(), Style::default().fg(Color::Yellow)),
+        );
+    }
+}
+
+fn paint_trace(
+    ctx: &mut Context,
+    data: &[ChartPoint],
+    color: Color,
+    value: fn(&ChartPoint) -> f64,
+) {
+    for window in data.windows(2) {
+        if let [start, end] = window {
+            ctx.draw(&Line {
+                x1: start.x,
+                y1: value(start),
+                x2: end.x,
+                y2: value(end),
+                color,
+            });
+        }
+    }
+}
+
+fn chart_block(title: &str, label: &str, highlight: bool) -> Block<'static> {
+    let block = Block::default()
+        .title(format!("{}: {}", title, label))
+        .borders(tui::widgets::Borders::ALL);
+    if highlight {
+        block.border_style(Style::default().fg(Color::Red))
+    } else {
+        block
     }
-    let sum: f64 = history.iter().map(|&(_, value)| value.into()).sum();
-    sum / (history.len() as f64)
 }
 
 fn collect_metrics(
-    cpu_tx: Sender<CPUMetrics>,
-    gpu_tx: Sender<GPUMetrics>,
-    netdisk_tx: Sender<NetDiskMetrics>,
+    interval_ms: u64,
+    samples: Option<u32>,
+    tx: Sender<Reading>,
     running: Arc<Mutex<bool>>,
 ) {
+    let interval = interval_ms.to_string();
+    let mut args = vec![
+        "--samplers".to_string(),
+        "cpu_power,gpu_power,thermal,network,disk".to_string(),
+        "-i".to_string(),
+        interval,
+    ];
+    // A fixed number of samples should all cover a real interval, not the time since boot
+    let show_initial_usage = samples.is_none();
+    match samples {
+        Some(n) => args.extend(["-n".to_string(), n.to_string()]),
+        None => args.push("--show-initial-usage".to_string()),
+    }
     let mut cmd = Command::new("powermetrics")
-        .args(&[
-            "--samplers",
-            "cpu_power,gpu_power,thermal,network,disk",
-            "--show-initial-usage",
-            "-i",
-            "1000",
-        ])
+        .args(&args)
         .stdout(Stdio::piped())
         .spawn()
         .expect("Failed to start powermetrics");
@@ -763,6 +1654,11 @@ fn collect_metrics(
     let mut cpu_metrics = CPUMetrics::new();
     let mut gpu_metrics = GPUMetrics::new();
     let mut netdisk_metrics = NetDiskMetrics::new();
+    let mut thermal_pressure = 0.0;
+    let mut in_sample = false;
+    let mut sent = false;
+    let mut seen = [false; SAMPLERS];
+    let mut stopped = false;
 
     for line in reader.lines() {
         let line = match line {
@@ -772,28 +1668,93 @@ fn collect_metrics(
 
         if !*running.lock().unwrap() {
             let _ = cmd.kill();
+            stopped = true;
             break;
         }
 
+        // A sample is sent at the blank line closing the section that completes it, or when the
+        // next header begins if a sampler printed nothing
+        let complete = line.trim().is_empty() && seen.iter().all(|&seen| seen);
+        let header = line.starts_with("*** Sampled system activity");
+        if in_sample && !sent && (complete || header) {
+            let _ = tx.send(Reading {
+                cpu: cpu_metrics.clone(),
+                gpu: gpu_metrics.clone(),
+                netdisk: netdisk_metrics.clone(),
+                thermal_pressure,
+            });
+            sent = true;
+        }
+        if header {
+            // The initial usage sample covers the time since boot, so it isn't given a duration
+            cpu_metrics.elapsed_ms = if in_sample || !show_initial_usage {
+                parse_elapsed_ms(&line)
+            } else {
+                0.0
+            };
+            in_sample = true;
+            sent = false;
+            seen = [false; SAMPLERS];
+            continue;
+        }
+        if let Some(sampler) = sampler_of(&line) {
+            seen[sampler] = true;
+        }
+
         parse_cpu_metrics(&line, &mut cpu_metrics);
         parse_gpu_metrics(&line, &mut gpu_metrics);
         parse_netdisk_metrics(&line, &mut netdisk_metrics);
+        if let Some(level) = parse_thermal_pressure(&line) {
+            thermal_pressure = level;
+        }
+    }
 
-        // Append to histories
-        cpu_metrics.append_e_cluster_active(cpu_metrics.e_cluster_active);
-        cpu_metrics.append_p_cluster_active(cpu_metrics.p_cluster_active);
-        cpu_metrics.append_ane_w((cpu_metrics.ane_w * 100.0 / 8.0).clamp(0.0, 100.0));
+    // The last sample may only be known to be complete once powermetrics exits
+    if in_sample && !sent && !stopped {
+        let _ = tx.send(Reading {
+            cpu: cpu_metrics,
+            gpu: gpu_metrics,
+            netdisk: netdisk_metrics,
+            thermal_pressure,
+        });
+    }
+    let _ = cmd.wait();
+}
 
-        cpu_metrics.append_cpu_w(cpu_metrics.cpu_w);
-        cpu_metrics.append_gpu_w(cpu_metrics.gpu_w);
-        cpu_metrics.append_package_w(cpu_metrics.package_w);
+// Samplers requested from powermetrics
+const SAMPLERS: usize = 5;
+
+// The sampler whose last value we read is on this line, if any
+fn sampler_of(line: &str) -> Option<usize> {
+    let line = line.trim_start();
+    if line.starts_with("Combined Power") {
+        Some(0)
+    } else if GPU_ACTIVE_RE.is_match(line) {
+        Some(1)
+    } else if line.starts_with("Current pressure level:") {
+        Some(2)
+    } else if line.starts_with("in:") {
+        Some(3)
+    } else if line.starts_with("write:") {
+        Some(4)
+    } else {
+        None
+    }
+}
 
-        gpu_metrics.append_active(gpu_metrics.active);
+fn parse_elapsed_ms(line: &str) -> f64 {
+    ELAPSED_RE
+        .captures(line)
+        .and_then(|caps| caps[1].parse().ok())
+        .unwrap_or(0.0)
+}
 
-        let _ = cpu_tx.send(cpu_metrics.clone());
-        let _ = gpu_tx.send(gpu_metrics.clone());
-        let _ = netdisk_tx.send(netdisk_metrics.clone());
-    }
+fn parse_thermal_pressure(line: &str) -> Option<f64> {
+    let name = line.trim().strip_prefix("Current pressure level:")?.trim();
+    sample::THERMAL_PRESSURE_LEVELS
+        .iter()
+        .position(|level| *level == name)
+        .map(|level| level as f64)
 }
 
 fn parse_cpu_metrics(line: &str, cpu_metrics: &mut CPUMetrics) {
@@ -875,6 +1836,7 @@ fn parse_netdisk_metrics(line: &str, netdisk_metrics: &mut NetDiskMetrics) {
     }
 }
 
+#[cfg(target_os = "macos")]
 fn get_memory_metrics() -> MemoryMetrics {
     unsafe {
         let mut vm_info: vm_statistics64_data_t = std::mem::zeroed();
@@ -889,14 +1851,7 @@ fn get_memory_metrics() -> MemoryMetrics {
         );
 
         if result != 0 {
-            return MemoryMetrics {
-                total: 0,
-                used: 0,
-                swap_total: 0,
-                swap_used: 0,
-                used_percent: 0.0,
-                used_percent_history: VecDeque::new(),
-            };
+            return MemoryMetrics::new();
         }
 
         let page_size = libc::sysconf(libc::_SC_PAGESIZE) as u64;
@@ -908,14 +1863,7 @@ fn get_memory_metrics() -> MemoryMetrics {
         let total = match get_total_memory() {
             Ok(val) => val,
             Err(_) => {
-                return MemoryMetrics {
-                    total: 0,
-                    used: 0,
-                    swap_total: 0,
-                    swap_used: 0,
-                    used_percent: 0.0,
-                    used_percent_history: VecDeque::new(),
-                }
+                return MemoryMetrics::new()
             }
         };
 
@@ -941,11 +1889,17 @@ fn get_memory_metrics() -> MemoryMetrics {
             swap_total,
             swap_used,
             used_percent: used_percent as f32,
-            used_percent_history: VecDeque::new(),
         }
     }
 }
 
+// Elsewhere mtop can only work with recordings, which carry their own memory figures
+#[cfg(not(target_os = "macos"))]
+fn get_memory_metrics() -> MemoryMetrics {
+    MemoryMetrics::new()
+}
+
+#[cfg(target_os = "macos")]
 fn get_swap_memory() -> Result<(u64, u64, u64), std::io::Error> {
     let output = Command::new("sysctl")
         .arg("vm.swapusage")
@@ -967,6 +1921,7 @@ fn get_swap_memory() -> Result<(u64, u64, u64), std::io::Error> {
     ))
 }
 
+#[cfg(target_os = "macos")]
 fn parse_size(size_str: &str, unit: &str) -> u64 {
     let size: f64 = size_str.parse().unwrap_or(0.0);
     match unit {
@@ -976,6 +1931,7 @@ fn parse_size(size_str: &str, unit: &str) -> u64 {
     }
 }
 
+#[cfg(target_os = "macos")]
 fn get_total_memory() -> Result<u64, std::io::Error> {
     let mut size: u64 = 0;
     let mut size_len = std::mem::size_of::<u64>();
@@ -1030,10 +1986,7 @@ fn get_sysctl_string(name: &str) -> Result<String, std::io::Error> {
             return Ok(parts[1].to_string());
         }
     }
-    Err(std::io::Error::new(
-        std::io::ErrorKind::Other,
-        "Failed to get sysctl string",
-    ))
+    Err(std::io::Error::other("Failed to get sysctl string"))
 }
 
 fn get_sysctl_int(name: &str) -> Result<i32, std::io::Error> {
@@ -1045,18 +1998,15 @@ fn get_sysctl_int(name: &str) -> Result<i32, std::io::Error> {
             return parts[1]
                 .trim()
                 .parse::<i32>()
-                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, format!("Parse error: {}", e)));
+                .map_err(|e| std::io::Error::other(format!("Parse error: {}", e)));
         }
     }
-    Err(std::io::Error::new(
-        std::io::ErrorKind::Other,
-        "Failed to get sysctl int",
-    ))
+    Err(std::io::Error::other("Failed to get sysctl int"))
 }
 
 fn get_gpu_core_count() -> Result<String, std::io::Error> {
     let output = Command::new("system_profiler")
-        .args(&["-detailLevel", "basic", "SPDisplaysDataType"])
+        .args(["-detailLevel", "basic", "SPDisplaysDataType"])
         .output()?;
     if output.status.success() {
         let output_str = String::from_utf8_lossy(&output.stdout);
@@ -1069,8 +2019,5 @@ fn get_gpu_core_count() -> Result<String, std::io::Error> {
             }
         }
     }
-    Err(std::io::Error::new(
-        std::io::ErrorKind::Other,
-        "Failed to get GPU core count",
-    ))
+    Err(std::io::Error::other("Failed to get GPU core count"))
 }
@@ -0,0 +1,87 @@
+use std::ffi::CString;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::SystemTime;
+
+use crossbeam_channel::Sender;
+
+// A named point in the timeline, e.g. where a training epoch starts
+#[derive(Clone)]
+pub struct Marker {
+    pub timestamp: SystemTime,
+    pub name: String,
+}
+
+impl Marker {
+    pub fn now(name: &str) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            name: name.trim().to_string(),
+        }
+    }
+}
+
+// Passes on each line written to a FIFO; the FIFO goes away with the listener
+pub struct MarkerFifo {
+    path: PathBuf,
+}
+
+impl MarkerFifo {
+    // Creates a FIFO at `path` (or reuses one)
+    pub fn listen(path: &Path, tx: Sender<String>) -> io::Result<Self> {
+        create(path)?;
+        let fifo = PathBuf::from(path);
+        thread::spawn(move || loop {
+            // Opening blocks until a writer arrives, and reading ends when the last one closes
+            let Ok(file) = File::open(&fifo) else {
+                return;
+            };
+            for line in BufReader::new(file).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if line.trim().is_empty() {
+                    continue;
+                }
+                if tx.send(line).is_err() {
+                    return;
+                }
+            }
+        });
+        Ok(Self {
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for MarkerFifo {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn create(path: &Path) -> io::Result<()> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.file_type().is_fifo() => {}
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "exists and is not a FIFO",
+            ))
+        }
+        Err(_) => {
+            let c_path = CString::new(path.as_os_str().as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            if unsafe { libc::mkfifo(c_path.as_ptr(), 0o622) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            // mtop runs as root, but the scripts adding markers usually don't
+            fs::set_permissions(path, fs::Permissions::from_mode(0o622))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,310 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use serde_json::{json, Value};
+
+use crate::alert::{self, Event};
+use crate::config::Notify;
+use crate::http::{self, Url};
+use crate::sample::{self, FIELDS};
+
+// How long exiting waits for the last alerts to be delivered
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(6);
+
+// Somewhere alerts are sent when they start firing and when they resolve
+pub trait Notifier {
+    fn notify(&mut self, event: &Event) -> io::Result<()>;
+
+    // A line for the dashboard's info panel, for notifiers with something to report
+    fn status(&self) -> Option<String> {
+        None
+    }
+}
+
+// Opens every notifier set up in the config
+pub fn open(config: &Notify, host: &str, chip: &str) -> io::Result<Vec<Box<dyn Notifier>>> {
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    if let Some(command) = &config.command {
+        notifiers.push(Box::new(CommandNotifier {
+            command: command.clone(),
+            host: host.to_string(),
+            chip: chip.to_string(),
+        }));
+    }
+    if let Some(path) = &config.log {
+        let out = OpenOptions::new().create(true).append(true).open(path)?;
+        notifiers.push(Box::new(LogNotifier { out }));
+    }
+    if let Some(url) = &config.webhook {
+        notifiers.push(Box::new(WebhookNotifier::open(
+            Url::parse(url)?,
+            Duration::from_millis(config.timeout_ms),
+            config.retries,
+            host,
+            chip,
+        )));
+    }
+    Ok(notifiers)
+}
+
+// Notifies every notifier, returning the last error; unlike sinks, failing ones are kept
+pub fn notify_all(notifiers: &mut [Box<dyn Notifier>], event: &Event) -> Option<io::Error> {
+    notifiers
+        .iter_mut()
+        .filter_map(|notifier| notifier.notify(event).err())
+        .last()
+}
+
+fn event_json(event: &Event, host: &str, chip: &str) -> Value {
+    json!({
+        "type": "alert",
+        "state": state(event),
+        "severity": event.rule.severity.to_string(),
+        "rule": event.rule.rule.to_string(),
+        "metric": FIELDS[event.rule.rule.condition.field()],
+        "value": event.value,
+        "timestamp": sample::format_timestamp(event.timestamp),
+        "since": sample::format_timestamp(event.since),
+        "message": event.to_string(),
+        "host": host,
+        "chip": chip,
+    })
+}
+
+fn state(event: &Event) -> &'static str {
+    if event.firing {
+        "firing"
+    } else {
+        "resolved"
+    }
+}
+
+// Runs a shell command per event with the details in MTOP_ALERT_* variables
+struct CommandNotifier {
+    command: String,
+    host: String,
+    chip: String,
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&mut self, event: &Event) -> io::Result<()> {
+        let field = event.rule.rule.condition.field();
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("MTOP_ALERT_STATE", state(event))
+            .env("MTOP_ALERT_SEVERITY", event.rule.severity.to_string())
+            .env("MTOP_ALERT_RULE", event.rule.rule.to_string())
+            .env("MTOP_ALERT_METRIC", FIELDS[field])
+            .env("MTOP_ALERT_VALUE", event.value.to_string())
+            .env(
+                "MTOP_ALERT_VALUE_TEXT",
+                alert::format_value(field, event.value),
+            )
+            .env(
+                "MTOP_ALERT_TIMESTAMP",
+                sample::format_timestamp(event.timestamp),
+            )
+            .env("MTOP_ALERT_SINCE", sample::format_timestamp(event.since))
+            .env("MTOP_ALERT_MESSAGE", event.to_string())
+            .env("MTOP_HOST", &self.host)
+            .env("MTOP_CHIP", &self.chip)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .spawn()?;
+        // A slow hook mustn't hold up sampling, but it still needs reaping
+        thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+// Appends one line per event
+struct LogNotifier {
+    out: File,
+}
+
+impl Notifier for LogNotifier {
+    fn notify(&mut self, event: &Event) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{} {}",
+            sample::format_timestamp(event.timestamp),
+            event
+        )
+    }
+}
+
+#[derive(Default)]
+struct Deliveries {
+    sent: u64,
+    failed: u64,
+    last_error: Option<String>,
+}
+
+// POSTs each event as JSON, retrying with backoff on its own thread
+struct WebhookNotifier {
+    // `tx` is taken when dropped to let the thread finish delivering; `done` disconnects once
+    // it has
+    tx: Option<Sender<Value>>,
+    done: Receiver<()>,
+    host: String,
+    chip: String,
+    deliveries: Arc<Mutex<Deliveries>>,
+}
+
+impl WebhookNotifier {
+    fn open(url: Url, timeout: Duration, retries: u32, host: &str, chip: &str) -> Self {
+        let (tx, rx) = unbounded::<Value>();
+        let deliveries = Arc::new(Mutex::new(Deliveries::default()));
+        let thread_deliveries = Arc::clone(&deliveries);
+        let (done_tx, done) = unbounded();
+        thread::spawn(move || {
+            for body in rx.iter() {
+                let body = body.to_string();
+                let mut result = Ok(());
+                for attempt in 0..=retries {
+                    result = http::post(&url, "application/json", &[], body.as_bytes(), timeout);
+                    if result.is_ok() {
+                        break;
+                    }
+                    if attempt < retries {
+                        thread::sleep(Duration::from_millis(500 << attempt.min(5)));
+                    }
+                }
+                let mut deliveries = thread_deliveries.lock().unwrap();
+                match result {
+                    Ok(()) => deliveries.sent += 1,
+                    Err(e) => {
+                        deliveries.failed += 1;
+                        deliveries.last_error = Some(e.to_string());
+                    }
+                }
+            }
+            drop(done_tx);
+        });
+        Self {
+            tx: Some(tx),
+            done,
+            host: host.to_string(),
+            chip: chip.to_string(),
+            deliveries,
+        }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&mut self, event: &Event) -> io::Result<()> {
+        let body = event_json(event, &self.host, &self.chip);
+        match &self.tx {
+            Some(tx) if tx.send(body).is_ok() => Ok(()),
+            _ => Err(io::Error::other("Webhook thread stopped")),
+        }
+    }
+
+    fn status(&self) -> Option<String> {
+        let deliveries = self.deliveries.lock().unwrap();
+        let mut status = format!(
+            "Webhook: {} sent, {} failed",
+            deliveries.sent, deliveries.failed
+        );
+        if let Some(error) = &deliveries.last_error {
+            status.push_str(&format!(" ({})", error));
+        }
+        Some(status)
+    }
+}
+
+impl Drop for WebhookNotifier {
+    // Alerts from the last moments of a run still get sent, unless the endpoint is so slow that
+    // waiting would hold up exiting; the thread is then left to die with the process
+    fn drop(&mut self) {
+        self.tx.take();
+        let _ = self.done.recv_timeout(SHUTDOWN_TIMEOUT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::time::{Instant, UNIX_EPOCH};
+
+    use super::*;
+    use crate::config::AlertRule;
+
+    // Answers each request with the next status, handing back the bodies it was sent
+    fn serve(statuses: &'static [&'static str]) -> (Url, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/hook", listener.local_addr().unwrap())).unwrap();
+        let (tx, rx) = unbounded();
+        thread::spawn(move || {
+            for (stream, status) in listener.incoming().zip(statuses) {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut length = 0;
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    line.clear();
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+                tx.send(String::from_utf8(body).unwrap()).unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    fn event() -> Event {
+        Event {
+            rule: AlertRule::new("package_w > 20".parse().unwrap()),
+            firing: true,
+            value: 25.0,
+            timestamp: UNIX_EPOCH + Duration::from_secs(60),
+            since: UNIX_EPOCH,
+        }
+    }
+
+    #[test]
+    fn webhook_posts_json_and_retries_server_errors() {
+        let (url, bodies) = serve(&["500 Internal Server Error", "200 OK"]);
+        let timeout = Duration::from_secs(2);
+        let mut webhook = WebhookNotifier::open(url, timeout, 1, "host", "chip");
+        webhook.notify(&event()).unwrap();
+
+        let first: Value = serde_json::from_str(&bodies.recv_timeout(timeout).unwrap()).unwrap();
+        assert_eq!(first["state"], "firing");
+        assert_eq!(first["metric"], "package_w");
+        assert_eq!(first["value"], 25.0);
+        assert_eq!(first["host"], "host");
+        assert_eq!(first["timestamp"], "1970-01-01T00:01:00.000Z");
+        let retried = bodies.recv_timeout(timeout).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&retried).unwrap(), first);
+
+        drop(webhook);
+        let status = bodies.recv_timeout(Duration::from_millis(100));
+        assert!(status.is_err(), "delivered more than once");
+    }
+
+    #[test]
+    fn dropping_webhook_gives_up_on_unresponsive_endpoint() {
+        // Accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let mut webhook = WebhookNotifier::open(url, Duration::from_secs(60), 0, "host", "chip");
+        webhook.notify(&event()).unwrap();
+
+        let start = Instant::now();
+        drop(webhook);
+        assert!(start.elapsed() < SHUTDOWN_TIMEOUT + Duration::from_secs(1));
+        drop(listener);
+    }
+}
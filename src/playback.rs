@@ -0,0 +1,138 @@
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::energy::format_elapsed;
+use crate::recording::Recording;
+use crate::Dashboard;
+
+const MIN_SPEED: f64 = 0.25;
+const MAX_SPEED: f64 = 64.0;
+
+// Feeds a recording to the dashboard on a clock that can be paused, sped up and moved
+pub struct Player {
+    recording: Recording,
+    // Where the recording's start sits on the dashboard's clock
+    base: Instant,
+    position: Duration,
+    next: usize,
+    next_marker: usize,
+    speed: f64,
+    paused: bool,
+    last_tick: Instant,
+}
+
+impl Player {
+    pub fn new(recording: Recording) -> Self {
+        let now = Instant::now();
+        Self {
+            recording,
+            base: now,
+            position: Duration::ZERO,
+            next: 0,
+            next_marker: 0,
+            speed: 1.0,
+            paused: false,
+            last_tick: now,
+        }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    // The dashboard's notion of now, for positioning charts
+    pub fn now(&self) -> Instant {
+        self.base + self.position
+    }
+
+    // Moves the clock on by the wall time since the last call and applies the samples it passed
+    pub fn advance(&mut self, dashboard: &mut Dashboard) -> bool {
+        let tick = Instant::now();
+        if !self.paused {
+            self.position += tick.duration_since(self.last_tick).mul_f64(self.speed);
+            if self.position >= self.length() {
+                self.position = self.length();
+                self.paused = true;
+            }
+        }
+        self.last_tick = tick;
+        self.catch_up(dashboard)
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    // Going backwards rebuilds the dashboard from the first sample
+    pub fn seek(&mut self, dashboard: &mut Dashboard, secs: f64) {
+        let target = (self.position.as_secs_f64() + secs).clamp(0.0, self.length().as_secs_f64());
+        if secs < 0.0 {
+            dashboard.reset();
+            self.next = 0;
+            self.next_marker = 0;
+        }
+        self.position = Duration::from_secs_f64(target);
+        self.catch_up(dashboard);
+    }
+
+    pub fn restart(&mut self, dashboard: &mut Dashboard) {
+        dashboard.reset();
+        self.position = Duration::ZERO;
+        self.next = 0;
+        self.next_marker = 0;
+        self.paused = false;
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.0).min(MAX_SPEED);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = (self.speed / 2.0).max(MIN_SPEED);
+    }
+
+    pub fn status(&self) -> String {
+        format!(
+            "{} / {}  {}x{}",
+            format_elapsed(self.position),
+            format_elapsed(self.length()),
+            self.speed,
+            if self.paused { "  paused" } else { "" }
+        )
+    }
+
+    fn offset(&self, index: usize) -> Duration {
+        self.since_start(self.recording.samples[index].timestamp)
+    }
+
+    fn since_start(&self, time: SystemTime) -> Duration {
+        time.duration_since(self.recording.start)
+            .unwrap_or_default()
+    }
+
+    fn length(&self) -> Duration {
+        match self.recording.samples.len() {
+            0 => Duration::ZERO,
+            n => self.offset(n - 1),
+        }
+    }
+
+    fn catch_up(&mut self, dashboard: &mut Dashboard) -> bool {
+        let mut applied = false;
+        while self.next < self.recording.samples.len() && self.offset(self.next) <= self.position {
+            let time = self.base + self.offset(self.next);
+            dashboard.apply(&self.recording.samples[self.next], time);
+            self.next += 1;
+            applied = true;
+        }
+        while let Some(marker) = self.recording.markers.get(self.next_marker) {
+            let offset = self.since_start(marker.timestamp);
+            if offset > self.position {
+                break;
+            }
+            dashboard.mark(self.base + offset, &marker.name);
+            self.next_marker += 1;
+            applied = true;
+        }
+        applied
+    }
+}
@@ -0,0 +1,128 @@
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::energy::Energy;
+use crate::export::Sink;
+use crate::sample::{self, Sample};
+use crate::AppleSiliconInfo;
+
+#[derive(Default)]
+struct State {
+    latest: Option<Sample>,
+    samples: u64,
+    energy: Energy,
+}
+
+// Serves the latest sample on /metrics, with energy and sample counts as counters
+pub struct PrometheusSink {
+    state: Arc<Mutex<State>>,
+}
+
+impl PrometheusSink {
+    pub fn serve(addr: &str, info: &AppleSiliconInfo) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let state = Arc::new(Mutex::new(State::default()));
+        let labels = format!(
+            "chip=\"{}\",e_cores=\"{}\",p_cores=\"{}\",gpu_cores=\"{}\"",
+            escape_label(&info.name),
+            info.e_core_count,
+            info.p_core_count,
+            escape_label(&info.gpu_core_count)
+        );
+
+        let server_state = Arc::clone(&state);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                // A misbehaving scraper only loses its own response
+                let _ = respond(stream, &server_state, &labels);
+            }
+        });
+
+        Ok(Self { state })
+    }
+}
+
+impl Sink for PrometheusSink {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.energy.add_sample(sample);
+        state.samples += 1;
+        state.latest = Some(*sample);
+        Ok(())
+    }
+}
+
+fn respond(stream: TcpStream, state: &Mutex<State>, labels: &str) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers; the request has no body we care about
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut stream = reader.into_inner();
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+    if !request_line.starts_with("GET ") || (path != "/metrics" && path != "/") {
+        return write!(
+            stream,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+    }
+
+    let body = render(&state.lock().unwrap(), labels);
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+fn render(state: &State, labels: &str) -> String {
+    let mut body = String::new();
+    if let Some(sample) = &state.latest {
+        for (name, value) in sample::FIELDS.iter().zip(sample.values()) {
+            if *name == "elapsed_ms" {
+                continue;
+            }
+            let _ = writeln!(body, "# TYPE mtop_{} gauge", name);
+            let _ = writeln!(body, "mtop_{}{{{}}} {}", name, labels, value);
+        }
+    }
+
+    let _ = writeln!(
+        body,
+        "# HELP mtop_energy_joules_total Energy integrated from power samples"
+    );
+    let _ = writeln!(body, "# TYPE mtop_energy_joules_total counter");
+    for (component, joules) in [
+        ("cpu", state.energy.cpu),
+        ("gpu", state.energy.gpu),
+        ("ane", state.energy.ane),
+        ("package", state.energy.package),
+    ] {
+        let _ = writeln!(
+            body,
+            "mtop_energy_joules_total{{{},component=\"{}\"}} {}",
+            labels, component, joules
+        );
+    }
+
+    let _ = writeln!(body, "# TYPE mtop_samples_total counter");
+    let _ = writeln!(body, "mtop_samples_total{{{}}} {}", labels, state.samples);
+    body
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
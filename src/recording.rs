@@ -0,0 +1,437 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use serde_json::{json, Value};
+
+use crate::export::Sink;
+use crate::marker::Marker;
+use crate::sample::{self, Sample};
+use crate::{get_sysctl_string, AppleSiliconInfo};
+
+const FORMAT: &str = "mtop-recording";
+const VERSION: u64 = 1;
+
+// A session written by --record (or --csv) along with what it was recorded on
+pub struct Recording {
+    pub info: AppleSiliconInfo,
+    pub os: String,
+    pub start: SystemTime,
+    pub interval_ms: u64,
+    pub samples: Vec<Sample>,
+    pub markers: Vec<Marker>,
+}
+
+// JSON Lines: a header object, then one array per sample of the offset from the
+// start in milliseconds followed by the values named in the header's `fields`,
+// interleaved with `{"type": "marker", "offset_ms", "name"}` objects
+pub struct RecordingSink {
+    out: BufWriter<File>,
+    start: SystemTime,
+}
+
+impl RecordingSink {
+    // `start` is when the first sample began; offsets are measured from it
+    pub fn create(
+        path: &Path,
+        info: &AppleSiliconInfo,
+        interval_ms: u64,
+        start: SystemTime,
+    ) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let os = get_sysctl_string("kern.osproductversion")
+            .map(|version| format!("macOS {}", version))
+            .unwrap_or_else(|_| "unknown".to_string());
+        let header = json!({
+            "format": FORMAT,
+            "version": VERSION,
+            "chip": info.name,
+            "e_cores": info.e_core_count,
+            "p_cores": info.p_core_count,
+            "gpu_cores": info.gpu_core_count,
+            "os": os,
+            "start": sample::format_timestamp(start),
+            "interval_ms": interval_ms,
+            "fields": sample::FIELDS,
+        });
+        writeln!(out, "{}", header)?;
+        out.flush()?;
+        Ok(Self { out, start })
+    }
+}
+
+impl RecordingSink {
+    fn offset_ms(&self, time: SystemTime) -> u128 {
+        time.duration_since(self.start)
+            .unwrap_or_default()
+            .as_millis()
+    }
+}
+
+impl Sink for RecordingSink {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let offset = self.offset_ms(sample.timestamp);
+        let mut line = format!("[{}", offset);
+        for value in sample.values() {
+            line.push_str(&format!(",{}", value));
+        }
+        line.push(']');
+        writeln!(self.out, "{}", line)?;
+        self.out.flush()
+    }
+
+    fn mark(&mut self, marker: &Marker) -> io::Result<()> {
+        let record = json!({
+            "type": "marker",
+            "offset_ms": self.offset_ms(marker.timestamp),
+            "name": marker.name,
+        });
+        writeln!(self.out, "{}", record)?;
+        self.out.flush()
+    }
+}
+
+// Reads a recording, or a file written by --csv which lacks the chip and OS details
+pub fn load(path: &Path) -> io::Result<Recording> {
+    let contents = fs::read_to_string(path)?;
+    if contents.starts_with('{') {
+        load_native(&contents)
+    } else {
+        load_csv(&contents)
+    }
+}
+
+fn invalid(line: usize, message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, message),
+    )
+}
+
+// `start` moved on by an offset in milliseconds read from line `line`
+fn at_offset(start: SystemTime, offset_ms: f64, line: usize) -> io::Result<SystemTime> {
+    Duration::try_from_secs_f64(offset_ms / 1000.0)
+        .ok()
+        .and_then(|offset| start.checked_add(offset))
+        .ok_or_else(|| invalid(line, &format!("invalid offset `{}`", offset_ms)))
+}
+
+// Hand-edited or damaged files could otherwise hold values nothing downstream expects
+fn check_sample(sample: &Sample, line: usize) -> io::Result<()> {
+    if let Some(value) = sample.values().iter().find(|value| !value.is_finite()) {
+        return Err(invalid(line, &format!("invalid number `{}`", value)));
+    }
+    if Duration::try_from_secs_f64(sample.elapsed_ms / 1000.0).is_err() {
+        return Err(invalid(
+            line,
+            &format!("invalid elapsed time `{}`", sample.elapsed_ms),
+        ));
+    }
+    Ok(())
+}
+
+fn load_native(contents: &str) -> io::Result<Recording> {
+    let mut lines = contents.lines().enumerate();
+    let header: Value = match lines.next() {
+        Some((_, line)) => serde_json::from_str(line).map_err(|e| invalid(1, &e.to_string()))?,
+        None => return Err(invalid(1, "missing header")),
+    };
+    if header["format"] != FORMAT {
+        return Err(invalid(1, "not an mtop recording"));
+    }
+    let version = header["version"].as_u64().unwrap_or(0);
+    if version > VERSION {
+        return Err(invalid(
+            1,
+            &format!("recording version {} needs a newer mtop", version),
+        ));
+    }
+    let start = header["start"]
+        .as_str()
+        .and_then(sample::parse_timestamp)
+        .ok_or_else(|| invalid(1, "invalid start time"))?;
+    // Fields are matched by name so recordings from other versions still load
+    let columns: Vec<Option<usize>> = header["fields"]
+        .as_array()
+        .ok_or_else(|| invalid(1, "missing fields"))?
+        .iter()
+        .map(|name| sample::FIELDS.iter().position(|field| name == field))
+        .collect();
+    let text = |key: &str| header[key].as_str().unwrap_or("unknown").to_string();
+    let count = |key: &str| header[key].as_i64().unwrap_or(0) as i32;
+    let info = AppleSiliconInfo {
+        name: text("chip"),
+        e_core_count: count("e_cores"),
+        p_core_count: count("p_cores"),
+        gpu_core_count: text("gpu_cores"),
+    };
+
+    let mut samples = Vec::new();
+    let mut markers = Vec::new();
+    for (i, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Value =
+            serde_json::from_str(line).map_err(|e| invalid(i + 1, &e.to_string()))?;
+        // Objects are other kinds of record, skipped by readers that don't know them
+        let Some(row) = record.as_array() else {
+            if record["type"] == "marker" {
+                let offset = record["offset_ms"].as_f64().unwrap_or(0.0);
+                markers.push(Marker {
+                    timestamp: at_offset(start, offset, i + 1)?,
+                    name: record["name"].as_str().unwrap_or_default().to_string(),
+                });
+            }
+            continue;
+        };
+        let number = |value: &Value| {
+            value
+                .as_f64()
+                .ok_or_else(|| invalid(i + 1, &format!("invalid number `{}`", value)))
+        };
+        let offset = number(row.first().ok_or_else(|| invalid(i + 1, "empty sample"))?)?;
+        let mut values = [0.0; sample::FIELDS.len()];
+        for (column, value) in columns.iter().zip(&row[1..]) {
+            if let Some(index) = column {
+                values[*index] = number(value)?;
+            }
+        }
+        let sample = Sample::from_values(at_offset(start, offset, i + 1)?, &values);
+        check_sample(&sample, i + 1)?;
+        samples.push(sample);
+    }
+
+    markers.sort_by_key(|marker| marker.timestamp);
+    Ok(Recording {
+        info,
+        os: text("os"),
+        start,
+        interval_ms: header["interval_ms"].as_u64().unwrap_or(0),
+        samples,
+        markers,
+    })
+}
+
+fn load_csv(contents: &str) -> io::Result<Recording> {
+    let mut lines = contents.lines().enumerate();
+    let header: Vec<&str> = match lines.next() {
+        Some((_, header)) => header.split(',').collect(),
+        None => return Err(invalid(1, "missing header")),
+    };
+    if header.first() != Some(&"timestamp") {
+        return Err(invalid(1, "expected a timestamp column first"));
+    }
+    // Columns are matched by name so older files with fewer columns still load
+    let columns: Vec<Option<usize>> = header[1..]
+        .iter()
+        .map(|name| sample::FIELDS.iter().position(|field| field == name))
+        .collect();
+    let marker_column = header.iter().position(|name| *name == "marker");
+
+    let mut samples = Vec::new();
+    let mut markers = Vec::new();
+    for (i, line) in lines {
+        if line.trim().is_empty() {
+            continue;
+        }
+        // Only the last column, where markers go, may hold commas
+        let cells: Vec<&str> = line.splitn(header.len(), ',').collect();
+        let timestamp =
+            sample::parse_timestamp(cells[0]).ok_or_else(|| invalid(i + 1, "invalid timestamp"))?;
+        if let Some(name) = marker_column.and_then(|column| cells.get(column)) {
+            if !name.is_empty() {
+                markers.push(Marker {
+                    timestamp,
+                    name: csv_text(name),
+                });
+                continue;
+            }
+        }
+        let mut values = [0.0; sample::FIELDS.len()];
+        for (column, cell) in columns.iter().zip(&cells[1..]) {
+            if let Some(index) = column {
+                values[*index] = cell
+                    .parse()
+                    .map_err(|_| invalid(i + 1, &format!("invalid number `{}`", cell)))?;
+            }
+        }
+        let sample = Sample::from_values(timestamp, &values);
+        check_sample(&sample, i + 1)?;
+        samples.push(sample);
+    }
+
+    let unknown = || "unknown".to_string();
+    Ok(Recording {
+        info: AppleSiliconInfo {
+            name: unknown(),
+            e_core_count: 0,
+            p_core_count: 0,
+            gpu_core_count: unknown(),
+        },
+        os: unknown(),
+        start: samples
+            .first()
+            .map_or(SystemTime::UNIX_EPOCH, |sample| sample.timestamp),
+        interval_ms: samples
+            .first()
+            .map_or(0, |sample| sample.elapsed_ms.round() as u64),
+        samples,
+        markers,
+    })
+}
+
+// A CSV cell's text, unquoted
+fn csv_text(cell: &str) -> String {
+    match cell
+        .strip_prefix('"')
+        .and_then(|cell| cell.strip_suffix('"'))
+    {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => cell.to_string(),
+    }
+}
+
+// A short session for tests: 20 samples over 22s at 1s intervals except for one 3s stall, with
+// the P-CPU held at 2000 MHz from 4s to 16s and a marker at 10s
+#[cfg(test)]
+pub const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/session.rec");
+
+#[cfg(test)]
+pub fn fixture() -> Recording {
+    load(Path::new(FIXTURE)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::CsvSink;
+
+    fn sample(start: SystemTime, offset_ms: u64, package_w: f64) -> Sample {
+        let timestamp = start + Duration::from_millis(offset_ms);
+        let mut sample = Sample::from_values(timestamp, &[0.0; sample::FIELDS.len()]);
+        sample.elapsed_ms = 1000.0;
+        sample.package_w = package_w;
+        sample.memory_used = 8 << 30;
+        sample
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("mtop-{}-{}", std::process::id(), name))
+    }
+
+    fn info() -> AppleSiliconInfo {
+        AppleSiliconInfo {
+            name: "Apple M2".to_string(),
+            e_core_count: 4,
+            p_core_count: 4,
+            gpu_core_count: "10".to_string(),
+        }
+    }
+
+    #[test]
+    fn fixture_loads() {
+        let recording = fixture();
+        assert_eq!(recording.info.name, "Apple M2");
+        assert_eq!(recording.info.gpu_core_count, "10");
+        assert_eq!(recording.os, "macOS 14.5");
+        assert_eq!(recording.interval_ms, 1000);
+        assert_eq!(recording.samples.len(), 20);
+        assert_eq!(recording.markers.len(), 1);
+        assert_eq!(recording.markers[0].name, "build");
+        assert_eq!(
+            recording.markers[0].timestamp,
+            recording.start + Duration::from_secs(10)
+        );
+    }
+
+    #[test]
+    fn round_trips_a_recording() {
+        let path = temp_path("recording.jsonl");
+        let start = sample::parse_timestamp("2024-05-01T12:00:00.000Z").unwrap();
+        let mut sink = RecordingSink::create(&path, &info(), 1000, start).unwrap();
+        sink.write(&sample(start, 1000, 10.5)).unwrap();
+        sink.write(&sample(start, 2000, 12.25)).unwrap();
+        drop(sink);
+
+        let recording = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(recording.info.name, "Apple M2");
+        assert_eq!(recording.info.p_core_count, 4);
+        assert_eq!(recording.start, start);
+        assert_eq!(recording.interval_ms, 1000);
+        assert_eq!(recording.samples.len(), 2);
+        let second = &recording.samples[1];
+        assert_eq!(second.package_w, 12.25);
+        assert_eq!(second.memory_used, 8 << 30);
+        assert_eq!(
+            second.timestamp.duration_since(recording.start).unwrap(),
+            Duration::from_millis(2000)
+        );
+    }
+
+    #[test]
+    fn reads_csv_exports() {
+        let path = temp_path("export.csv");
+        let start = sample::parse_timestamp("2024-05-01T12:00:00.000Z").unwrap();
+        let mut sink = CsvSink::create(&path).unwrap();
+        sink.write(&sample(start, 0, 10.5)).unwrap();
+        sink.mark(&Marker {
+            timestamp: start + Duration::from_millis(500),
+            name: "epoch 1, \"warm\"".to_string(),
+        })
+        .unwrap();
+        sink.write(&sample(start, 1000, 12.25)).unwrap();
+        drop(sink);
+
+        let recording = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(recording.info.name, "unknown");
+        assert_eq!(recording.start, start);
+        assert_eq!(recording.interval_ms, 1000);
+        assert_eq!(recording.samples.len(), 2);
+        assert_eq!(recording.samples[1].package_w, 12.25);
+        assert_eq!(recording.samples[1].memory_used, 8 << 30);
+        assert_eq!(recording.markers.len(), 1);
+        assert_eq!(recording.markers[0].name, "epoch 1, \"warm\"");
+        assert_eq!(
+            recording.markers[0].timestamp,
+            start + Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn matches_csv_columns_by_name() {
+        let csv = "timestamp,package_w,elapsed_ms\n2024-05-01T12:00:00.000Z,7.5,500\n";
+        let recording = load_csv(csv).unwrap();
+        assert_eq!(recording.samples[0].package_w, 7.5);
+        assert_eq!(recording.samples[0].elapsed_ms, 500.0);
+        assert_eq!(recording.samples[0].cpu_w, 0.0);
+    }
+
+    #[test]
+    fn rejects_malformed_recordings() {
+        let header = r#"{"format":"mtop-recording","version":1,"start":"2024-05-01T12:00:00.000Z","fields":["elapsed_ms","package_w"]}"#;
+        let error = |body: &str| {
+            load_native(&format!("{}\n{}\n", header, body))
+                .err()
+                .map(|e| e.to_string())
+        };
+        assert!(error("[1000, 1000, 7.5]").is_none());
+        assert_eq!(
+            error("[-1000, 1000, 7.5]").unwrap(),
+            "line 2: invalid offset `-1000`"
+        );
+        assert_eq!(
+            error("[1000, -5, 7.5]").unwrap(),
+            "line 2: invalid elapsed time `-5`"
+        );
+        assert!(error(r#"{"type":"marker","offset_ms":1e300,"name":"x"}"#).is_some());
+
+        let csv = "timestamp,package_w\n2024-05-01T12:00:00.000Z,NaN\n";
+        assert!(load_csv(csv).is_err());
+        let csv = "timestamp,package_w\n99999-05-01T12:00:00.000Z,7.5\n";
+        assert!(load_csv(csv).is_err());
+    }
+}
@@ -0,0 +1,397 @@
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, ExitStatus};
+use std::time::{Duration, Instant};
+
+use crossbeam_channel::{unbounded, Receiver, RecvTimeoutError};
+use serde_json::{json, Value};
+
+use crate::cli::RunArgs;
+use crate::config::{Config, Units};
+use crate::sample::Sample;
+use crate::summary::{self, Summary};
+use crate::{get_memory_metrics, spawn_collector, AppleSiliconInfo, Reading};
+
+// One run of the command and the samples covering it
+pub struct Measurement {
+    pub wall: Duration,
+    pub status: ExitStatus,
+    pub summary: Summary,
+}
+
+// Runs the command under powermetrics and prints a report, returning the command's exit code
+pub fn run(
+    args: &RunArgs,
+    config: &Config,
+    json: bool,
+    model_info: &AppleSiliconInfo,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let (tx, rx) = unbounded();
+    let running = spawn_collector(config.interval_ms, None, &tx);
+    drop(tx);
+
+    let result = run_all(args, &rx);
+    *running.lock().unwrap() = false;
+    let (baseline, measurements, failed_warmup) = result?;
+
+    // A failing run ends the benchmark with that run's status
+    let last = &measurements[measurements.len() - 1];
+    if !last.status.success() {
+        let run = match failed_warmup {
+            Some(warmup) => format!("warmup run {}", warmup),
+            None => format!("run {}", measurements.len()),
+        };
+        eprintln!(
+            "'{}' failed on {} with exit code {}",
+            args.command.join(" "),
+            run,
+            exit_code(last.status)
+        );
+        return Ok(exit_code(last.status));
+    }
+
+    // The report goes to stderr so the command's own output stays clean
+    let single = measurements.len() == 1 && baseline.is_none();
+    if json {
+        let mut record = summary::info_json(model_info);
+        record["command"] = json!(args.command);
+        record["exit_code"] = json!(exit_code(last.status));
+        if single {
+            record["wall_seconds"] = json!(last.wall.as_secs_f64());
+            record["summary"] = last.summary.to_json();
+        } else {
+            record["runs"] = json!(measurements.len());
+            record["warmup"] = json!(args.warmup);
+            record["baseline_w"] = json!(baseline);
+            for (name, values) in run_metrics(&measurements, baseline.unwrap_or(0.0)) {
+                record[name] = RunStats::new(&values).to_json();
+            }
+        }
+        eprintln!("{}", record);
+    } else if single {
+        eprintln!(
+            "\n Performance stats for '{}' on {}:\n",
+            args.command.join(" "),
+            summary::format_info(model_info)
+        );
+        eprintln!("{}", format_report(last, &config.units));
+    } else {
+        let mut details = vec![format!("{} runs", measurements.len())];
+        if args.warmup > 0 {
+            details.push(format!("{} warmup", args.warmup));
+        }
+        if let Some(watts) = baseline {
+            details.push(format!("idle baseline of {:.2} W subtracted", watts));
+        }
+        eprintln!(
+            "\n Performance stats for '{}' on {} ({}):\n",
+            args.command.join(" "),
+            summary::format_info(model_info),
+            details.join(", ")
+        );
+        eprintln!(
+            "{}",
+            format_stats(&run_metrics(&measurements, baseline.unwrap_or(0.0)))
+        );
+    }
+    Ok(exit_code(last.status))
+}
+
+// Optional idle baseline, then warmup runs, then measured runs until one fails; a failing warmup
+// is returned alone along with its number
+fn run_all(
+    args: &RunArgs,
+    rx: &Receiver<Reading>,
+) -> io::Result<(Option<f64>, Vec<Measurement>, Option<u32>)> {
+    let baseline = match args.baseline {
+        Some(secs) => {
+            eprintln!("Measuring idle power for {}s...", secs);
+            Some(measure_idle(rx, Duration::from_secs(secs))?.mean("package_w"))
+        }
+        None => None,
+    };
+
+    for i in 0..args.warmup {
+        eprintln!("Warmup {}/{}...", i + 1, args.warmup);
+        let warmup = measure(&args.command, rx)?;
+        if !warmup.status.success() {
+            return Ok((baseline, vec![warmup], Some(i + 1)));
+        }
+    }
+
+    let mut measurements = Vec::new();
+    for i in 0..args.repeat {
+        if args.repeat > 1 {
+            eprintln!("Run {}/{}...", i + 1, args.repeat);
+        }
+        let measurement = measure(&args.command, rx)?;
+        let failed = !measurement.status.success();
+        measurements.push(measurement);
+        if failed {
+            break;
+        }
+    }
+    Ok((baseline, measurements, None))
+}
+
+// Samples an idle machine for at least `duration`, in whole sample intervals
+fn measure_idle(rx: &Receiver<Reading>, duration: Duration) -> io::Result<Summary> {
+    let stopped = || io::Error::other("powermetrics stopped sampling");
+    while rx.try_recv().is_ok() {}
+    rx.recv().map_err(|_| stopped())?;
+
+    let started = Instant::now();
+    let mut samples = Vec::new();
+    while started.elapsed() < duration {
+        let reading = rx.recv().map_err(|_| stopped())?;
+        samples.push(Sample::new(&reading, &get_memory_metrics()));
+    }
+    Ok(Summary::new(&samples))
+}
+
+// Starts the command on a sample boundary and keeps sampling until the sample it exits in completes
+pub fn measure(command: &[String], rx: &Receiver<Reading>) -> io::Result<Measurement> {
+    let stopped = || io::Error::other("powermetrics stopped sampling");
+    while rx.try_recv().is_ok() {}
+    rx.recv().map_err(|_| stopped())?;
+
+    let started = Instant::now();
+    let mut child = Command::new(&command[0]).args(&command[1..]).spawn()?;
+    let mut samples = Vec::new();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        match rx.recv_timeout(Duration::from_millis(20)) {
+            Ok(reading) => samples.push(Sample::new(&reading, &get_memory_metrics())),
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => {
+                let _ = child.kill();
+                return Err(stopped());
+            }
+        }
+    };
+    let wall = started.elapsed();
+    let reading = rx.recv().map_err(|_| stopped())?;
+    samples.push(Sample::new(&reading, &get_memory_metrics()));
+
+    Ok(Measurement {
+        wall,
+        status,
+        summary: Summary::new(&samples),
+    })
+}
+
+fn format_report(measurement: &Measurement, units: &Units) -> String {
+    let summary = &measurement.summary;
+    let power = |field: &str| {
+        format!(
+            "avg {}  peak {}",
+            units.format_power(summary.mean(field)),
+            units.format_power(summary.max(field))
+        )
+    };
+    let energy = &summary.energy;
+    let mut lines = vec![
+        format!(
+            "   Wall time:        {:.3} s",
+            measurement.wall.as_secs_f64()
+        ),
+        format!("   CPU Power:        {}", power("cpu_w")),
+        format!("   GPU Power:        {}", power("gpu_w")),
+        format!("   ANE Power:        {}", power("ane_w")),
+        format!("   Package Power:    {}", power("package_w")),
+        format!(
+            "   Energy:           {:.1} J  (CPU {:.1} J, GPU {:.1} J, ANE {:.1} J)",
+            energy.package, energy.cpu, energy.gpu, energy.ane
+        ),
+        format!(
+            "   E-CPU Residency:  {:.1}%",
+            summary.mean("e_cpu_active_percent")
+        ),
+        format!(
+            "   P-CPU Residency:  {:.1}%",
+            summary.mean("p_cpu_active_percent")
+        ),
+        format!(
+            "   GPU Residency:    {:.1}%",
+            summary.mean("gpu_active_percent")
+        ),
+        format!(
+            "   Peak Memory:      {}",
+            units.format_memory(summary.max("memory_used_bytes") as u64)
+        ),
+        format!(
+            "   Sampled:          {:.3} s in {} samples",
+            summary.seconds, summary.samples
+        ),
+    ];
+    if summary.throttled_intervals > 0 {
+        lines.push(format!(
+            "   Throttling:       {} likely interval(s) over {:.1} s",
+            summary.throttled_intervals, summary.throttled_seconds
+        ));
+    }
+    lines.join("\n")
+}
+
+// Per-run duration, energy and average package power, with the idle baseline taken off
+fn run_metrics(measurements: &[Measurement], baseline_w: f64) -> Vec<(&'static str, Vec<f64>)> {
+    vec![
+        (
+            "duration_s",
+            measurements.iter().map(|m| m.wall.as_secs_f64()).collect(),
+        ),
+        (
+            "energy_j",
+            measurements
+                .iter()
+                .map(|m| m.summary.energy.package - baseline_w * m.summary.seconds)
+                .collect(),
+        ),
+        (
+            "avg_package_w",
+            measurements
+                .iter()
+                .map(|m| m.summary.mean("package_w") - baseline_w)
+                .collect(),
+        ),
+    ]
+}
+
+fn format_stats(metrics: &[(&str, Vec<f64>)]) -> String {
+    let mut lines = vec![format!(
+        "   {:<16} {:>10} {:>10} {:>10} {:>10}   {}",
+        "", "mean", "stddev", "min", "max", "95% CI"
+    )];
+    for (name, values) in metrics {
+        let label = match *name {
+            "duration_s" => "Duration (s)",
+            "energy_j" => "Energy (J)",
+            _ => "Package (W)",
+        };
+        let stats = RunStats::new(values);
+        lines.push(format!(
+            "   {:<16} {:>10.3} {:>10.3} {:>10.3} {:>10.3}   {:.3} .. {:.3}",
+            label, stats.mean, stats.stddev, stats.min, stats.max, stats.ci95.0, stats.ci95.1
+        ));
+    }
+    lines.join("\n")
+}
+
+// Mean, sample standard deviation, range and a Student's t 95% confidence interval for the mean
+struct RunStats {
+    mean: f64,
+    stddev: f64,
+    min: f64,
+    max: f64,
+    ci95: (f64, f64),
+}
+
+impl RunStats {
+    fn new(values: &[f64]) -> Self {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let stddev = if values.len() > 1 {
+            (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        let margin = t_critical_95(values.len().saturating_sub(1)) * stddev / n.sqrt();
+        Self {
+            mean,
+            stddev,
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            ci95: (mean - margin, mean + margin),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "mean": self.mean,
+            "stddev": self.stddev,
+            "min": self.min,
+            "max": self.max,
+            "ci95": [self.ci95.0, self.ci95.1],
+        })
+    }
+}
+
+// Two-sided 95% critical values of Student's t distribution by degrees of freedom
+fn t_critical_95(df: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    // Beyond the table, standard values in between which t is close to linear in 1 / df
+    const BEYOND: [(f64, f64); 5] = [
+        (30.0, 2.042),
+        (40.0, 2.021),
+        (60.0, 2.000),
+        (120.0, 1.980),
+        (f64::INFINITY, 1.960),
+    ];
+    if df == 0 {
+        return 0.0;
+    }
+    if df <= TABLE.len() {
+        return TABLE[df - 1];
+    }
+    let x = 1.0 / df as f64;
+    BEYOND
+        .windows(2)
+        .find(|pair| df as f64 <= pair[1].0)
+        .map(|pair| {
+            let ((df0, t0), (df1, t1)) = (pair[0], pair[1]);
+            let (x0, x1) = (1.0 / df0, 1.0 / df1);
+            t0 + (t1 - t0) * (x0 - x) / (x0 - x1)
+        })
+        .unwrap_or(1.960)
+}
+
+// Mirrors the shell: a command killed by a signal exits with 128 + the signal number
+pub fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarises_runs_with_a_confidence_interval() {
+        let stats = RunStats::new(&[10.0, 12.0, 14.0]);
+        assert_eq!(stats.mean, 12.0);
+        assert_eq!(stats.stddev, 2.0);
+        assert_eq!((stats.min, stats.max), (10.0, 14.0));
+        // t for 2 degrees of freedom is 4.303
+        let margin = 4.303 * 2.0 / 3f64.sqrt();
+        assert!((stats.ci95.0 - (12.0 - margin)).abs() < 1e-9);
+        assert!((stats.ci95.1 - (12.0 + margin)).abs() < 1e-9);
+
+        let single = RunStats::new(&[5.0]);
+        assert_eq!(single.stddev, 0.0);
+        assert_eq!(single.ci95, (5.0, 5.0));
+    }
+
+    #[test]
+    fn t_critical_values_follow_the_table() {
+        assert_eq!(t_critical_95(0), 0.0);
+        assert_eq!(t_critical_95(1), 12.706);
+        assert_eq!(t_critical_95(30), 2.042);
+        assert_eq!(t_critical_95(40), 2.021);
+        assert_eq!(t_critical_95(60), 2.0);
+        assert_eq!(t_critical_95(120), 1.98);
+        // In between, t follows 1 / df rather than df
+        assert!((t_critical_95(50) - 2.0084).abs() < 1e-4);
+        assert!((t_critical_95(1_000_000) - 1.96).abs() < 1e-4);
+        for df in 1..1000 {
+            assert!(t_critical_95(df + 1) <= t_critical_95(df), "df {}", df);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{MemoryMetrics, Reading};
+
+// Everything known about one powermetrics interval, flattened for export
+#[derive(Clone, Copy)]
+pub struct Sample {
+    pub timestamp: SystemTime,
+    pub elapsed_ms: f64,
+    pub e_cpu_active: f64,
+    pub e_cpu_freq_mhz: f64,
+    pub p_cpu_active: f64,
+    pub p_cpu_freq_mhz: f64,
+    pub gpu_active: f64,
+    pub gpu_freq_mhz: f64,
+    pub cpu_w: f64,
+    pub gpu_w: f64,
+    pub ane_w: f64,
+    pub package_w: f64,
+    pub memory_used: u64,
+    pub memory_total: u64,
+    pub swap_used: u64,
+    pub swap_total: u64,
+    pub net_in_bytes_per_sec: f64,
+    pub net_out_bytes_per_sec: f64,
+    pub net_in_packets_per_sec: f64,
+    pub net_out_packets_per_sec: f64,
+    pub disk_read_kbytes_per_sec: f64,
+    pub disk_write_kbytes_per_sec: f64,
+    pub disk_read_ops_per_sec: f64,
+    pub disk_write_ops_per_sec: f64,
+    pub thermal_pressure: f64,
+}
+
+// Exported field names; the suffix or the name itself carries the unit
+pub const FIELDS: [&str; 24] = [
+    "elapsed_ms",
+    "e_cpu_active_percent",
+    "e_cpu_freq_mhz",
+    "p_cpu_active_percent",
+    "p_cpu_freq_mhz",
+    "gpu_active_percent",
+    "gpu_freq_mhz",
+    "cpu_w",
+    "gpu_w",
+    "ane_w",
+    "package_w",
+    "memory_used_bytes",
+    "memory_total_bytes",
+    "swap_used_bytes",
+    "swap_total_bytes",
+    "net_in_bytes_per_sec",
+    "net_out_bytes_per_sec",
+    "net_in_packets_per_sec",
+    "net_out_packets_per_sec",
+    "disk_read_kbytes_per_sec",
+    "disk_write_kbytes_per_sec",
+    "disk_read_ops_per_sec",
+    "disk_write_ops_per_sec",
+    "thermal_pressure_level",
+];
+
+// powermetrics' thermal pressure levels from least to most severe, stored by index
+pub const THERMAL_PRESSURE_LEVELS: [&str; 5] =
+    ["Nominal", "Moderate", "Heavy", "Trapping", "Sleeping"];
+
+pub fn thermal_pressure_name(level: f64) -> &'static str {
+    THERMAL_PRESSURE_LEVELS
+        .get(level.round().max(0.0) as usize)
+        .copied()
+        .unwrap_or("Unknown")
+}
+
+impl Sample {
+    pub fn new(reading: &Reading, memory: &MemoryMetrics) -> Self {
+        Self {
+            timestamp: SystemTime::now(),
+            elapsed_ms: reading.cpu.elapsed_ms,
+            e_cpu_active: reading.cpu.e_cluster_active as f64,
+            e_cpu_freq_mhz: reading.cpu.e_cluster_freq_mhz as f64,
+            p_cpu_active: reading.cpu.p_cluster_active as f64,
+            p_cpu_freq_mhz: reading.cpu.p_cluster_freq_mhz as f64,
+            gpu_active: reading.gpu.active,
+            gpu_freq_mhz: reading.gpu.freq_mhz as f64,
+            cpu_w: reading.cpu.cpu_w,
+            gpu_w: reading.cpu.gpu_w,
+            ane_w: reading.cpu.ane_w,
+            package_w: reading.cpu.package_w,
+            memory_used: memory.used,
+            memory_total: memory.total,
+            swap_used: memory.swap_used,
+            swap_total: memory.swap_total,
+            net_in_bytes_per_sec: reading.netdisk.in_bytes_per_sec,
+            net_out_bytes_per_sec: reading.netdisk.out_bytes_per_sec,
+            net_in_packets_per_sec: reading.netdisk.in_packets_per_sec,
+            net_out_packets_per_sec: reading.netdisk.out_packets_per_sec,
+            disk_read_kbytes_per_sec: reading.netdisk.read_kbytes_per_sec,
+            disk_write_kbytes_per_sec: reading.netdisk.write_kbytes_per_sec,
+            disk_read_ops_per_sec: reading.netdisk.read_ops_per_sec,
+            disk_write_ops_per_sec: reading.netdisk.write_ops_per_sec,
+            thermal_pressure: reading.thermal_pressure,
+        }
+    }
+
+    // The inverse of `values`, for samples read back from a file
+    pub fn from_values(timestamp: SystemTime, values: &[f64; FIELDS.len()]) -> Self {
+        Self {
+            timestamp,
+            elapsed_ms: values[0],
+            e_cpu_active: values[1],
+            e_cpu_freq_mhz: values[2],
+            p_cpu_active: values[3],
+            p_cpu_freq_mhz: values[4],
+            gpu_active: values[5],
+            gpu_freq_mhz: values[6],
+            cpu_w: values[7],
+            gpu_w: values[8],
+            ane_w: values[9],
+            package_w: values[10],
+            memory_used: values[11] as u64,
+            memory_total: values[12] as u64,
+            swap_used: values[13] as u64,
+            swap_total: values[14] as u64,
+            net_in_bytes_per_sec: values[15],
+            net_out_bytes_per_sec: values[16],
+            net_in_packets_per_sec: values[17],
+            net_out_packets_per_sec: values[18],
+            disk_read_kbytes_per_sec: values[19],
+            disk_write_kbytes_per_sec: values[20],
+            disk_read_ops_per_sec: values[21],
+            disk_write_ops_per_sec: values[22],
+            thermal_pressure: values[23],
+        }
+    }
+
+    // When the interval this sample covers began; a nonsensical elapsed time counts as none
+    pub fn start(&self) -> SystemTime {
+        Duration::try_from_secs_f64(self.elapsed_ms / 1000.0)
+            .ok()
+            .and_then(|elapsed| self.timestamp.checked_sub(elapsed))
+            .unwrap_or(self.timestamp)
+    }
+
+    // Values in the same order as FIELDS
+    pub fn values(&self) -> [f64; FIELDS.len()] {
+        [
+            self.elapsed_ms,
+            self.e_cpu_active,
+            self.e_cpu_freq_mhz,
+            self.p_cpu_active,
+            self.p_cpu_freq_mhz,
+            self.gpu_active,
+            self.gpu_freq_mhz,
+            self.cpu_w,
+            self.gpu_w,
+            self.ane_w,
+            self.package_w,
+            self.memory_used as f64,
+            self.memory_total as f64,
+            self.swap_used as f64,
+            self.swap_total as f64,
+            self.net_in_bytes_per_sec,
+            self.net_out_bytes_per_sec,
+            self.net_in_packets_per_sec,
+            self.net_out_packets_per_sec,
+            self.disk_read_kbytes_per_sec,
+            self.disk_write_kbytes_per_sec,
+            self.disk_read_ops_per_sec,
+            self.disk_write_ops_per_sec,
+            self.thermal_pressure,
+        ]
+    }
+}
+
+// RFC 3339 in UTC with millisecond precision, e.g. 2024-05-01T12:00:00.250Z
+pub fn format_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+// Reads timestamps written by `format_timestamp`; only UTC is accepted
+pub fn parse_timestamp(text: &str) -> Option<SystemTime> {
+    let text = text.strip_suffix('Z')?;
+    let (date, time) = text.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let (time, fraction) = time.split_once('.').unwrap_or((time, "0"));
+    let mut time = time.splitn(3, ':').map(|part| part.parse::<u64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    let nanos: u32 = format!("{:0<9}", fraction).get(..9)?.parse().ok()?;
+    // Out-of-range parts would overflow the arithmetic below
+    if !(0..=9999).contains(&year) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let days = days_from_civil(year, month, day);
+    let secs = u64::try_from(days).ok()? * 86400 + hour * 3600 + minute * 60 + second;
+    UNIX_EPOCH.checked_add(Duration::new(secs, nanos))
+}
+
+// Days since 1970-01-01 to a proleptic Gregorian date (Howard Hinnant's algorithm)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
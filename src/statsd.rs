@@ -0,0 +1,117 @@
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::config::Statsd;
+use crate::export::{self, Sink};
+use crate::sample::{self, Sample};
+
+// Keeps each datagram within a typical Ethernet MTU
+const MAX_DATAGRAM: usize = 1432;
+
+// Sends the mean of each metric over every flush interval as a gauge, weighted by the time
+// each sample measured as the dashboard's averages are
+pub struct StatsdSink {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    prefix: String,
+    tags: String,
+    flush_interval: Duration,
+    last_flush: Instant,
+    weighted_sums: [f64; sample::FIELDS.len()],
+    weight: f64,
+    sums: [f64; sample::FIELDS.len()],
+    count: u32,
+}
+
+impl StatsdSink {
+    pub fn open(config: &Statsd, addr: &str) -> io::Result<Self> {
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Host has no addresses"))?;
+        let prefix = if config.prefix.is_empty() {
+            String::new()
+        } else {
+            format!("{}.", config.prefix.trim_end_matches('.'))
+        };
+        let tags = if config.tags.is_empty() {
+            String::new()
+        } else {
+            format!("|#{}", config.tags.join(","))
+        };
+        Ok(Self {
+            socket: export::udp_socket_for(&addr)?,
+            addr,
+            prefix,
+            tags,
+            flush_interval: Duration::from_millis(config.flush_interval_ms),
+            last_flush: Instant::now(),
+            weighted_sums: [0.0; sample::FIELDS.len()],
+            weight: 0.0,
+            sums: [0.0; sample::FIELDS.len()],
+            count: 0,
+        })
+    }
+
+    fn flush(&mut self) {
+        if self.count == 0 {
+            return;
+        }
+        let mut datagram = String::new();
+        for (i, name) in sample::FIELDS.iter().enumerate() {
+            if *name == "elapsed_ms" {
+                continue;
+            }
+            // Samples without a measured interval count equally
+            let mean = if self.weight > 0.0 {
+                self.weighted_sums[i] / self.weight
+            } else {
+                self.sums[i] / self.count as f64
+            };
+            let line = format!("{}{}:{}|g{}", self.prefix, name, mean, self.tags);
+            if !datagram.is_empty() && datagram.len() + 1 + line.len() > MAX_DATAGRAM {
+                self.send(&datagram);
+                datagram.clear();
+            }
+            if !datagram.is_empty() {
+                datagram.push('\n');
+            }
+            datagram.push_str(&line);
+        }
+        self.send(&datagram);
+        self.weighted_sums = [0.0; sample::FIELDS.len()];
+        self.weight = 0.0;
+        self.sums = [0.0; sample::FIELDS.len()];
+        self.count = 0;
+        self.last_flush = Instant::now();
+    }
+
+    // StatsD is fire-and-forget, so a daemon that isn't listening yet doesn't stop the sink
+    fn send(&self, datagram: &str) {
+        let _ = self.socket.send_to(datagram.as_bytes(), self.addr);
+    }
+}
+
+impl Sink for StatsdSink {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let weight = sample.elapsed_ms.max(0.0) / 1000.0;
+        for (i, value) in sample.values().into_iter().enumerate() {
+            self.weighted_sums[i] += value * weight;
+            self.sums[i] += value;
+        }
+        self.weight += weight;
+        self.count += 1;
+        if self.last_flush.elapsed() >= self.flush_interval {
+            self.flush();
+        }
+        Ok(())
+    }
+}
+
+// Sends the means of samples since the last flush when mtop exits
+impl Drop for StatsdSink {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
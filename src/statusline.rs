@@ -0,0 +1,239 @@
+use std::io::{self, IsTerminal, Write};
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde_json::json;
+
+use crate::condition;
+use crate::export::Sink;
+use crate::sample::{self, Sample};
+
+// How each line is wrapped for the program showing it
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Format {
+    /// Plain text, rewritten in place on a terminal
+    Plain,
+    /// One line per sample for `#(...)` in tmux's status-right
+    Tmux,
+    /// The i3bar JSON protocol, also read by swaybar
+    I3bar,
+    /// JSON for a waybar custom module with "return-type": "json"
+    Waybar,
+    /// xbar and SwiftBar plugin output, streamed with `~~~` between updates
+    Xbar,
+}
+
+const PRESETS: [(&str, Format, &str); 5] = [
+    (
+        "tmux",
+        Format::Tmux,
+        "#[fg=green]E {e_cpu}% #[fg=cyan]P {p_cpu}% #[fg=magenta]G {gpu}%#[default] {package_w:.1}W",
+    ),
+    ("i3bar", Format::I3bar, "P {p_cpu}% G {gpu}% {package_w:.1}W"),
+    ("waybar", Format::Waybar, "P {p_cpu}% G {gpu}% {package_w:.1}W"),
+    ("xbar", Format::Xbar, "{package_w:.1}W"),
+    ("swiftbar", Format::Xbar, "{package_w:.1}W"),
+];
+
+// Text with `{metric}` or `{metric:.N}` placeholders, or the name of a preset
+#[derive(Clone)]
+pub struct Template {
+    pieces: Vec<Piece>,
+    preset: Option<Format>,
+}
+
+#[derive(Clone)]
+enum Piece {
+    Text(String),
+    Field {
+        index: usize,
+        precision: Option<usize>,
+    },
+}
+
+impl Template {
+    pub fn render(&self, sample: &Sample) -> String {
+        let values = sample.values();
+        self.pieces
+            .iter()
+            .map(|piece| match piece {
+                Piece::Text(text) => text.clone(),
+                Piece::Field { index, precision } => {
+                    format_field(sample::FIELDS[*index], values[*index], *precision)
+                }
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, String> {
+        if let Some((_, format, preset)) = PRESETS.iter().find(|(name, ..)| *name == text) {
+            let mut template = Self::from_str(preset)?;
+            template.preset = Some(*format);
+            return Ok(template);
+        }
+
+        let mut pieces = Vec::new();
+        let mut rest = text;
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed `{{` in `{}`", text))?;
+            if open > 0 {
+                pieces.push(Piece::Text(rest[..open].to_string()));
+            }
+            let placeholder = &rest[open + 1..open + close];
+            let (name, spec) = placeholder.split_once(':').unwrap_or((placeholder, ""));
+            let precision = match spec {
+                "" => None,
+                _ => Some(
+                    spec.strip_prefix('.')
+                        .and_then(|digits| digits.parse().ok())
+                        .ok_or_else(|| {
+                            format!("expected `.N` after `:` in `{{{}}}`", placeholder)
+                        })?,
+                ),
+            };
+            pieces.push(Piece::Field {
+                index: condition::resolve_field(name.trim())?,
+                precision,
+            });
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            pieces.push(Piece::Text(rest.to_string()));
+        }
+        Ok(Self {
+            pieces,
+            preset: None,
+        })
+    }
+}
+
+// Byte counts read best in GB and levels by name; everything else is whole unless it's watts
+fn format_field(name: &str, value: f64, precision: Option<usize>) -> String {
+    if name.ends_with("_level") {
+        return sample::thermal_pressure_name(value).to_string();
+    }
+    let (value, default) = if name.ends_with("_bytes") {
+        (value / 1024.0 / 1024.0 / 1024.0, 1)
+    } else if name.ends_with("_w") {
+        (value, 1)
+    } else {
+        (value, 0)
+    };
+    format!("{:.*}", precision.unwrap_or(default), value)
+}
+
+// Every headline metric, for tooltips and dropdown menus
+fn details(sample: &Sample) -> Vec<String> {
+    let gb = |bytes: u64| bytes as f64 / 1024.0 / 1024.0 / 1024.0;
+    vec![
+        format!(
+            "E-CPU {:.0}% @ {:.0} MHz",
+            sample.e_cpu_active, sample.e_cpu_freq_mhz
+        ),
+        format!(
+            "P-CPU {:.0}% @ {:.0} MHz",
+            sample.p_cpu_active, sample.p_cpu_freq_mhz
+        ),
+        format!(
+            "GPU {:.0}% @ {:.0} MHz",
+            sample.gpu_active, sample.gpu_freq_mhz
+        ),
+        format!(
+            "Power: CPU {:.2} W, GPU {:.2} W, ANE {:.2} W, Package {:.2} W",
+            sample.cpu_w, sample.gpu_w, sample.ane_w, sample.package_w
+        ),
+        format!(
+            "Memory {:.1}/{:.1} GB, Swap {:.1}/{:.1} GB",
+            gb(sample.memory_used),
+            gb(sample.memory_total),
+            gb(sample.swap_used),
+            gb(sample.swap_total)
+        ),
+        format!(
+            "Thermal pressure: {}",
+            sample::thermal_pressure_name(sample.thermal_pressure)
+        ),
+    ]
+}
+
+// One refreshed status line per sample on stdout
+pub struct StatusLineSink {
+    template: Template,
+    format: Format,
+    out: io::Stdout,
+    in_place: bool,
+    updates: u64,
+}
+
+impl StatusLineSink {
+    pub fn new(template: &Template, format: Option<Format>) -> io::Result<Self> {
+        let format = format.or(template.preset).unwrap_or(Format::Plain);
+        let out = io::stdout();
+        let in_place = format == Format::Plain && out.is_terminal();
+        if format == Format::I3bar {
+            let mut lock = out.lock();
+            writeln!(lock, "{}", json!({ "version": 1 }))?;
+            writeln!(lock, "[")?;
+            lock.flush()?;
+        }
+        Ok(Self {
+            template: template.clone(),
+            format,
+            out,
+            in_place,
+            updates: 0,
+        })
+    }
+}
+
+impl Sink for StatusLineSink {
+    fn write(&mut self, sample: &Sample) -> io::Result<()> {
+        let text = self.template.render(sample);
+        let mut out = self.out.lock();
+        match self.format {
+            Format::Plain if self.in_place => write!(out, "\r\x1b[K{}", text)?,
+            Format::Plain | Format::Tmux => writeln!(out, "{}", text)?,
+            Format::I3bar => {
+                // The protocol is an endless array, so every update after the first needs a comma
+                let separator = if self.updates > 0 { "," } else { "" };
+                let block = json!([{ "name": "mtop", "full_text": text }]);
+                writeln!(out, "{}{}", separator, block)?;
+            }
+            Format::Waybar => {
+                let record = json!({
+                    "text": text,
+                    "tooltip": details(sample).join("\n"),
+                    "class": "mtop",
+                });
+                writeln!(out, "{}", record)?;
+            }
+            Format::Xbar => {
+                if self.updates > 0 {
+                    writeln!(out, "~~~")?;
+                }
+                writeln!(out, "{}", text)?;
+                writeln!(out, "---")?;
+                for line in details(sample) {
+                    writeln!(out, "{}", line)?;
+                }
+            }
+        }
+        self.updates += 1;
+        out.flush()
+    }
+}
+
+impl Drop for StatusLineSink {
+    fn drop(&mut self) {
+        // Leave the shell prompt on a line of its own
+        if self.in_place && self.updates > 0 {
+            let _ = writeln!(self.out);
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use serde_json::{json, Map, Value};
+
+use crate::config::Units;
+use crate::energy::{format_energy, Energy};
+use crate::sample::{self, Sample};
+use crate::throttle::ThrottleDetector;
+use crate::AppleSiliconInfo;
+
+const FIELD_COUNT: usize = sample::FIELDS.len();
+
+// Time-weighted averages, 95th percentiles, peaks and energy over a run of samples
+#[derive(Clone)]
+pub struct Summary {
+    pub samples: usize,
+    pub seconds: f64,
+    pub mean: [f64; FIELD_COUNT],
+    pub p50: [f64; FIELD_COUNT],
+    pub p95: [f64; FIELD_COUNT],
+    pub max: [f64; FIELD_COUNT],
+    pub energy: Energy,
+    // Likely thermal throttling, judged against the run's own peak frequencies
+    pub throttled_intervals: usize,
+    pub throttled_seconds: f64,
+}
+
+impl Summary {
+    pub fn new(samples: &[Sample]) -> Self {
+        let seconds: f64 = samples.iter().map(|s| s.elapsed_ms / 1000.0).sum();
+        // Samples without a known duration count equally
+        let weights: Vec<f64> = samples
+            .iter()
+            .map(|sample| {
+                if seconds > 0.0 {
+                    sample.elapsed_ms / 1000.0 / seconds
+                } else {
+                    1.0 / samples.len() as f64
+                }
+            })
+            .collect();
+        let rows: Vec<[f64; FIELD_COUNT]> = samples.iter().map(Sample::values).collect();
+
+        let mut mean = [0.0; FIELD_COUNT];
+        let mut p50 = [0.0; FIELD_COUNT];
+        let mut p95 = [0.0; FIELD_COUNT];
+        let mut max = [0.0; FIELD_COUNT];
+        for i in 0..FIELD_COUNT {
+            let mut column: Vec<(f64, f64)> = rows
+                .iter()
+                .zip(&weights)
+                .map(|(row, &w)| (row[i], w))
+                .collect();
+            mean[i] = column.iter().map(|&(value, w)| value * w).sum();
+            max[i] = column
+                .iter()
+                .map(|&(value, _)| value)
+                .fold(f64::NEG_INFINITY, f64::max);
+            column.sort_by(|a, b| a.0.total_cmp(&b.0));
+            p50[i] = weighted_percentile(&column, 50.0);
+            p95[i] = weighted_percentile(&column, 95.0);
+        }
+        if samples.is_empty() {
+            max = [0.0; FIELD_COUNT];
+        }
+
+        let mut energy = Energy::default();
+        let mut throttle = ThrottleDetector::with_max(
+            max[field_index("p_cpu_freq_mhz")],
+            max[field_index("gpu_freq_mhz")],
+        );
+        for sample in samples {
+            energy.add_sample(sample);
+            throttle.update(sample);
+        }
+        Self {
+            samples: samples.len(),
+            seconds,
+            mean,
+            p50,
+            p95,
+            max,
+            energy,
+            throttled_intervals: throttle.intervals.len(),
+            throttled_seconds: throttle.seconds(),
+        }
+    }
+
+    pub fn mean(&self, field: &str) -> f64 {
+        self.mean[field_index(field)]
+    }
+
+    pub fn p50(&self, field: &str) -> f64 {
+        self.p50[field_index(field)]
+    }
+
+    pub fn p95(&self, field: &str) -> f64 {
+        self.p95[field_index(field)]
+    }
+
+    pub fn max(&self, field: &str) -> f64 {
+        self.max[field_index(field)]
+    }
+
+    pub fn to_json(&self) -> Value {
+        let fields = |values: &[f64; FIELD_COUNT]| {
+            let map: Map<String, Value> = sample::FIELDS
+                .iter()
+                .zip(values)
+                .map(|(name, value)| (name.to_string(), json!(value)))
+                .collect();
+            Value::Object(map)
+        };
+        json!({
+            "samples": self.samples,
+            "seconds": self.seconds,
+            "mean": fields(&self.mean),
+            "p50": fields(&self.p50),
+            "p95": fields(&self.p95),
+            "max": fields(&self.max),
+            "energy_joules": {
+                "cpu": self.energy.cpu,
+                "gpu": self.energy.gpu,
+                "ane": self.energy.ane,
+                "package": self.energy.package,
+            },
+            "throttling": {
+                "intervals": self.throttled_intervals,
+                "seconds": self.throttled_seconds,
+            },
+        })
+    }
+
+    // Every dashboard panel as text; peaks are only worth showing once there is more than one sample
+    pub fn format(&self, units: &Units) -> String {
+        let peaks = self.samples > 1;
+        let percent = |field: &str| {
+            let mut text = format!("{:.1}%", self.mean(field));
+            if peaks {
+                text.push_str(&format!(
+                    " (p50 {:.1}%, p95 {:.1}%, max {:.1}%)",
+                    self.p50(field),
+                    self.p95(field),
+                    self.max(field)
+                ));
+            }
+            text
+        };
+        let power = |field: &str| {
+            let mut text = units.format_power(self.mean(field));
+            if peaks {
+                text.push_str(&format!(
+                    " (p50 {}, p95 {}, max {})",
+                    units.format_power(self.p50(field)),
+                    units.format_power(self.p95(field)),
+                    units.format_power(self.max(field))
+                ));
+            }
+            text
+        };
+        let memory = |field: &str| units.format_memory(self.mean(field) as u64);
+
+        let mut lines = vec![if peaks {
+            format!(
+                "{} samples over {:.1}s (time-weighted averages)",
+                self.samples, self.seconds
+            )
+        } else {
+            format!("1 sample over {:.1}s", self.seconds)
+        }];
+        lines.push(format!(
+            "E-CPU:         {} @ {:.0} MHz",
+            percent("e_cpu_active_percent"),
+            self.mean("e_cpu_freq_mhz")
+        ));
+        lines.push(format!(
+            "P-CPU:         {} @ {:.0} MHz",
+            percent("p_cpu_active_percent"),
+            self.mean("p_cpu_freq_mhz")
+        ));
+        lines.push(format!(
+            "GPU:           {} @ {:.0} MHz",
+            percent("gpu_active_percent"),
+            self.mean("gpu_freq_mhz")
+        ));
+        lines.push(format!("CPU Power:     {}", power("cpu_w")));
+        lines.push(format!("GPU Power:     {}", power("gpu_w")));
+        lines.push(format!("ANE Power:     {}", power("ane_w")));
+        lines.push(format!("Package Power: {}", power("package_w")));
+        lines.push(format!(
+            "Energy:        {}",
+            format_energy(self.energy.package)
+        ));
+        lines.push(format!(
+            "Memory:        {} / {}  Swap: {} / {}",
+            memory("memory_used_bytes"),
+            memory("memory_total_bytes"),
+            memory("swap_used_bytes"),
+            memory("swap_total_bytes")
+        ));
+        lines.push(format!(
+            "Network:       in {:.1} bytes/s, out {:.1} bytes/s",
+            self.mean("net_in_bytes_per_sec"),
+            self.mean("net_out_bytes_per_sec")
+        ));
+        lines.push(format!(
+            "Disk:          read {:.1} KB/s, write {:.1} KB/s",
+            self.mean("disk_read_kbytes_per_sec"),
+            self.mean("disk_write_kbytes_per_sec")
+        ));
+        if self.throttled_intervals > 0 {
+            lines.push(format!(
+                "Throttling:    {} likely interval(s) over {:.1}s",
+                self.throttled_intervals, self.throttled_seconds
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+pub fn format_info(info: &AppleSiliconInfo) -> String {
+    format!(
+        "{} ({} E-Cores, {} P-Cores, {} GPU Cores)",
+        info.name, info.e_core_count, info.p_core_count, info.gpu_core_count
+    )
+}
+
+pub fn info_json(info: &AppleSiliconInfo) -> Value {
+    json!({
+        "chip": info.name,
+        "e_cores": info.e_core_count,
+        "p_cores": info.p_core_count,
+        "gpu_cores": info.gpu_core_count.parse::<i32>().ok(),
+    })
+}
+
+// Values sorted ascending with weights summing to one
+fn weighted_percentile(sorted: &[(f64, f64)], p: f64) -> f64 {
+    let target = p / 100.0;
+    let mut seen = 0.0;
+    for &(value, weight) in sorted {
+        seen += weight;
+        if seen >= target {
+            return value;
+        }
+    }
+    sorted.last().map_or(0.0, |&(value, _)| value)
+}
+
+pub fn field_index(field: &str) -> usize {
+    sample::FIELDS
+        .iter()
+        .position(|name| *name == field)
+        .unwrap_or_else(|| panic!("unknown sample field `{}`", field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    // A sample measuring `elapsed_ms` at `package_w`, with the CPU drawing half of it
+    fn sample(elapsed_ms: f64, package_w: f64) -> Sample {
+        Sample {
+            timestamp: UNIX_EPOCH,
+            elapsed_ms,
+            e_cpu_active: 50.0,
+            e_cpu_freq_mhz: 1000.0,
+            p_cpu_active: package_w,
+            p_cpu_freq_mhz: 3000.0,
+            gpu_active: 0.0,
+            gpu_freq_mhz: 0.0,
+            cpu_w: package_w / 2.0,
+            gpu_w: 0.0,
+            ane_w: 0.0,
+            package_w,
+            memory_used: 8 << 30,
+            memory_total: 16 << 30,
+            swap_used: 0,
+            swap_total: 0,
+            net_in_bytes_per_sec: 0.0,
+            net_out_bytes_per_sec: 0.0,
+            net_in_packets_per_sec: 0.0,
+            net_out_packets_per_sec: 0.0,
+            disk_read_kbytes_per_sec: 0.0,
+            disk_write_kbytes_per_sec: 0.0,
+            disk_read_ops_per_sec: 0.0,
+            disk_write_ops_per_sec: 0.0,
+            thermal_pressure: 0.0,
+        }
+    }
+
+    #[test]
+    fn weights_samples_by_elapsed_time() {
+        let summary = Summary::new(&[sample(1000.0, 10.0), sample(3000.0, 30.0)]);
+        assert_eq!(summary.samples, 2);
+        assert_eq!(summary.seconds, 4.0);
+        // Counting each sample once would give 20 W
+        assert_eq!(summary.mean("package_w"), 25.0);
+        assert_eq!(summary.max("package_w"), 30.0);
+        assert_eq!(summary.mean("memory_used_bytes"), (8u64 << 30) as f64);
+        assert_eq!(summary.energy.package, 100.0);
+        assert_eq!(summary.energy.cpu, 50.0);
+
+        let json = summary.to_json();
+        assert_eq!(json["samples"], 2);
+        assert_eq!(json["mean"]["package_w"], 25.0);
+        assert_eq!(json["p50"]["package_w"], 30.0);
+        assert_eq!(json["energy_joules"]["package"], 100.0);
+
+        let text = summary.format(&Units::default());
+        assert!(text.starts_with("2 samples over 4.0s (time-weighted averages)"));
+        assert!(text.contains("P-CPU:         25.0% (p50 30.0%, p95 30.0%, max 30.0%) @ 3000 MHz"));
+    }
+
+    #[test]
+    fn counts_samples_equally_without_durations() {
+        let summary = Summary::new(&[sample(0.0, 10.0), sample(0.0, 30.0)]);
+        assert_eq!(summary.seconds, 0.0);
+        assert_eq!(summary.mean("package_w"), 20.0);
+        assert_eq!(summary.energy.package, 0.0);
+    }
+
+    #[test]
+    fn summarises_nothing_as_zeros() {
+        let summary = Summary::new(&[]);
+        assert_eq!(summary.samples, 0);
+        assert_eq!(summary.mean("package_w"), 0.0);
+        assert_eq!(summary.max("package_w"), 0.0);
+    }
+
+    #[test]
+    fn leaves_out_peaks_for_a_single_sample() {
+        let text = Summary::new(&[sample(1000.0, 10.0)]).format(&Units::default());
+        assert!(text.starts_with("1 sample over 1.0s"));
+        assert!(text.contains("P-CPU:         10.0% @ 3000 MHz"));
+    }
+}
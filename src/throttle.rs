@@ -0,0 +1,231 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::sample::Sample;
+
+// A cluster at least this busy, running this far below the highest frequency seen for at
+// least this long, is most likely being held back by the thermal controller
+const MIN_RESIDENCY: f64 = 80.0;
+const MAX_FREQ_RATIO: f64 = 0.85;
+const MIN_DURATION: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Domain {
+    PCpu,
+    Gpu,
+}
+
+const DOMAINS: [Domain; 2] = [Domain::PCpu, Domain::Gpu];
+
+impl Domain {
+    pub fn name(self) -> &'static str {
+        match self {
+            Domain::PCpu => "P-CPU",
+            Domain::Gpu => "GPU",
+        }
+    }
+
+    // Residency and frequency
+    fn read(self, sample: &Sample) -> (f64, f64) {
+        match self {
+            Domain::PCpu => (sample.p_cpu_active, sample.p_cpu_freq_mhz),
+            Domain::Gpu => (sample.gpu_active, sample.gpu_freq_mhz),
+        }
+    }
+}
+
+// A stretch of likely throttling; `end` moves on while it lasts
+pub struct Interval {
+    pub domain: Domain,
+    pub start: SystemTime,
+    pub end: SystemTime,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Track {
+    max_mhz: f64,
+    since: Option<SystemTime>,
+    active: bool,
+}
+
+pub struct ThrottleDetector {
+    tracks: [Track; 2],
+    pub intervals: Vec<Interval>,
+}
+
+impl ThrottleDetector {
+    pub fn new() -> Self {
+        Self {
+            tracks: [Track::default(); 2],
+            intervals: Vec::new(),
+        }
+    }
+
+    // Starts from known peak frequencies, e.g. those of a whole recording, rather than
+    // only those seen so far
+    pub fn with_max(p_cpu_mhz: f64, gpu_mhz: f64) -> Self {
+        let mut detector = Self::new();
+        detector.tracks[Domain::PCpu as usize].max_mhz = p_cpu_mhz;
+        detector.tracks[Domain::Gpu as usize].max_mhz = gpu_mhz;
+        detector
+    }
+
+    pub fn update(&mut self, sample: &Sample) {
+        let start = sample.start();
+        for (domain, track) in DOMAINS.into_iter().zip(&mut self.tracks) {
+            let (residency, freq_mhz) = domain.read(sample);
+            track.max_mhz = track.max_mhz.max(freq_mhz);
+            let throttled = residency >= MIN_RESIDENCY && freq_mhz < track.max_mhz * MAX_FREQ_RATIO;
+            if !throttled {
+                track.since = None;
+                track.active = false;
+                continue;
+            }
+
+            let since = *track.since.get_or_insert(start);
+            if track.active {
+                if let Some(interval) = self
+                    .intervals
+                    .iter_mut()
+                    .rev()
+                    .find(|interval| interval.domain == domain)
+                {
+                    interval.end = sample.timestamp;
+                }
+            } else if sample.timestamp.duration_since(since).unwrap_or_default() >= MIN_DURATION {
+                track.active = true;
+                self.intervals.push(Interval {
+                    domain,
+                    start: since,
+                    end: sample.timestamp,
+                });
+            }
+        }
+    }
+
+    pub fn active(&self, domain: Domain) -> bool {
+        self.tracks[domain as usize].active
+    }
+
+    pub fn seconds(&self) -> f64 {
+        self.seconds_since(UNIX_EPOCH)
+    }
+
+    // Intervals still going after `since`
+    pub fn count_since(&self, since: SystemTime) -> usize {
+        self.intervals
+            .iter()
+            .filter(|interval| interval.end > since)
+            .count()
+    }
+
+    // Time throttled after `since`; intervals that began before it only count from it
+    pub fn seconds_since(&self, since: SystemTime) -> f64 {
+        // Adding zero turns the -0.0 an empty sum gives into 0.0, which JSON shows as `0.0`
+        self.intervals
+            .iter()
+            .filter(|interval| interval.end > since)
+            .map(|interval| {
+                interval
+                    .end
+                    .duration_since(interval.start.max(since))
+                    .unwrap_or_default()
+                    .as_secs_f64()
+            })
+            .sum::<f64>()
+            + 0.0
+    }
+
+    // e.g. "2 intervals over 70.0s (P-CPU 1, GPU 1)", or None if there weren't any
+    pub fn summary(&self) -> Option<String> {
+        if self.intervals.is_empty() {
+            return None;
+        }
+        let counts: Vec<String> = DOMAINS
+            .iter()
+            .map(|domain| {
+                let count = self
+                    .intervals
+                    .iter()
+                    .filter(|interval| interval.domain == *domain)
+                    .count();
+                format!("{} {}", domain.name(), count)
+            })
+            .collect();
+        Some(format!(
+            "{} interval{} over {:.1}s ({})",
+            self.intervals.len(),
+            if self.intervals.len() == 1 { "" } else { "s" },
+            self.seconds(),
+            counts.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+    use crate::sample;
+
+    // One-second samples of a busy P-CPU at 3.2 GHz, held to 2.4 GHz from 4s to 16s
+    fn run() -> Vec<Sample> {
+        (1..=20)
+            .map(|secs| {
+                let timestamp = UNIX_EPOCH + Duration::from_secs(secs);
+                let mut sample = Sample::from_values(timestamp, &[0.0; sample::FIELDS.len()]);
+                sample.elapsed_ms = 1000.0;
+                sample.p_cpu_active = 90.0;
+                sample.p_cpu_freq_mhz = if (5..=16).contains(&secs) {
+                    2400.0
+                } else {
+                    3200.0
+                };
+                sample
+            })
+            .collect()
+    }
+
+    #[test]
+    fn detects_a_long_stretch_below_peak_frequency() {
+        let mut detector = ThrottleDetector::new();
+        for sample in &run() {
+            detector.update(sample);
+        }
+        // From when the first slow sample began until the last slow one ended
+        assert_eq!(detector.intervals.len(), 1);
+        let interval = &detector.intervals[0];
+        assert!(interval.domain == Domain::PCpu);
+        assert_eq!(interval.start, UNIX_EPOCH + Duration::from_secs(4));
+        assert_eq!(interval.end, UNIX_EPOCH + Duration::from_secs(16));
+        assert_eq!(detector.seconds(), 12.0);
+        assert!(!detector.active(Domain::PCpu));
+        assert_eq!(
+            detector.summary().unwrap(),
+            "1 interval over 12.0s (P-CPU 1, GPU 0)"
+        );
+    }
+
+    #[test]
+    fn ignores_short_stretches() {
+        let mut detector = ThrottleDetector::new();
+        // Slow for 9s by the 13th sample
+        for sample in &run()[..13] {
+            detector.update(sample);
+        }
+        assert!(detector.intervals.is_empty());
+        assert!(detector.summary().is_none());
+        assert!(detector.seconds().is_sign_positive());
+    }
+
+    #[test]
+    fn judges_against_known_peaks() {
+        // Against a 4 GHz peak 3.2 GHz is throttled too
+        let mut detector = ThrottleDetector::with_max(4000.0, 0.0);
+        for sample in &run() {
+            detector.update(sample);
+        }
+        assert_eq!(detector.intervals.len(), 1);
+        assert_eq!(detector.intervals[0].start, UNIX_EPOCH);
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Raw samples cover the recent past; older data is only kept as min/avg/max rollups
+const RAW_RETENTION: Duration = Duration::from_secs(600);
+const TIERS: [(Duration, Duration); 3] = [
+    (Duration::from_secs(10), Duration::from_secs(2 * 3600)),
+    (Duration::from_secs(60), Duration::from_secs(12 * 3600)),
+    (Duration::from_secs(600), Duration::from_secs(24 * 3600)),
+];
+
+// Longest span a series can answer queries for
+pub const MAX_RETENTION: Duration = TIERS[TIERS.len() - 1].1;
+
+// A raw sample or a rollup bucket; `weight` is the time in seconds it accounts for and
+// `count` the samples in it
+#[derive(Clone, Copy)]
+pub struct Point {
+    pub time: Instant,
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    pub weight: f64,
+    pub count: u32,
+}
+
+// A window's statistics; chart titles show them after the latest value, which comes straight
+// from the sample
+#[derive(Clone, Copy, Default)]
+pub struct Stats {
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub p50: f64,
+    pub p95: f64,
+}
+
+impl Stats {
+    // The same statistics in other units, e.g. watts as a share of a nominal peak
+    pub fn scaled(self, factor: f64) -> Self {
+        Self {
+            mean: self.mean * factor,
+            min: self.min * factor,
+            max: self.max * factor,
+            p50: self.p50 * factor,
+            p95: self.p95 * factor,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Bucket {
+    start: Instant,
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: u32,
+    weighted_sum: f64,
+    weight: f64,
+}
+
+impl Bucket {
+    fn point(&self) -> Point {
+        let avg = if self.weight > 0.0 {
+            self.weighted_sum / self.weight
+        } else {
+            self.sum / self.count as f64
+        };
+        Point {
+            time: self.start,
+            min: self.min,
+            avg,
+            max: self.max,
+            weight: self.weight,
+            count: self.count,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Tier {
+    resolution: Duration,
+    retention: Duration,
+    buckets: VecDeque<Bucket>,
+}
+
+impl Tier {
+    fn push(&mut self, origin: Instant, time: Instant, value: f64, weight: f64) {
+        if let Some(last) = self.buckets.back_mut() {
+            if time < last.start + self.resolution {
+                last.min = last.min.min(value);
+                last.max = last.max.max(value);
+                last.sum += value;
+                last.count += 1;
+                last.weighted_sum += value * weight;
+                last.weight += weight;
+                return;
+            }
+        }
+        // Align buckets to the series' origin so they line up across tiers
+        let offset = time.saturating_duration_since(origin).as_nanos() / self.resolution.as_nanos();
+        let start = origin + self.resolution * offset as u32;
+        self.buckets.push_back(Bucket {
+            start,
+            min: value,
+            max: value,
+            sum: value,
+            count: 1,
+            weighted_sum: value * weight,
+            weight,
+        });
+        retain_since(&mut self.buckets, |b| b.start, time, self.retention);
+    }
+}
+
+#[derive(Clone)]
+struct Totals {
+    weighted_sum: f64,
+    weight: f64,
+    sum: f64,
+    count: u64,
+    min: f64,
+    max: f64,
+}
+
+impl Totals {
+    fn new() -> Self {
+        Self {
+            weighted_sum: 0.0,
+            weight: 0.0,
+            sum: 0.0,
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    fn add(&mut self, value: f64, weight: f64) {
+        self.weighted_sum += value * weight;
+        self.weight += weight;
+        self.sum += value;
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn mean(&self) -> f64 {
+        if self.weight > 0.0 {
+            self.weighted_sum / self.weight
+        } else if self.count > 0 {
+            self.sum / self.count as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TimeSeries<T> {
+    origin: Option<Instant>,
+    raw: VecDeque<(Instant, T, f64)>,
+    raw_retention: Duration,
+    tiers: Vec<Tier>,
+    session: Totals,
+}
+
+impl<T> TimeSeries<T>
+where
+    T: Into<f64> + Copy,
+{
+    pub fn new() -> Self {
+        Self {
+            origin: None,
+            raw: VecDeque::new(),
+            raw_retention: RAW_RETENTION,
+            tiers: TIERS
+                .iter()
+                .map(|&(resolution, retention)| Tier {
+                    resolution,
+                    retention,
+                    buckets: VecDeque::new(),
+                })
+                .collect(),
+            session: Totals::new(),
+        }
+    }
+
+    // Times come from the caller so recordings can be replayed on their own clock. `weight` is
+    // the seconds the value accounts for: powermetrics' own elapsed time for the sample, so a
+    // late or stalled sample still counts for exactly the interval it measured, as in energy totals
+    pub fn push_at(&mut self, time: Instant, value: T, weight: f64) {
+        let origin = *self.origin.get_or_insert(time);
+        let weight = weight.max(0.0);
+        self.raw.push_back((time, value, weight));
+        retain_since(&mut self.raw, |&(t, _, _)| t, time, self.raw_retention);
+        for tier in &mut self.tiers {
+            tier.push(origin, time, value.into(), weight);
+        }
+        self.session.add(value.into(), weight);
+    }
+
+    // The latest value pushed, if any
+    pub fn last(&self) -> Option<T> {
+        self.raw.back().map(|&(_, value, _)| value)
+    }
+
+    // How far back points keep every sample rather than rollups
+    pub fn raw_retention(&self) -> Duration {
+        self.raw_retention
+    }
+
+    // How far back points reach at all
+    pub fn retention(&self) -> Duration {
+        self.tiers
+            .iter()
+            .map(|tier| tier.retention)
+            .max()
+            .unwrap_or(self.raw_retention)
+    }
+
+    // Points covering the window ending at `now`, at the finest resolution still retained for it
+    pub fn points(&self, now: Instant, window: Duration) -> Vec<Point> {
+        let cutoff = now.checked_sub(window);
+        let after = |time: Instant| cutoff.is_none_or(|cutoff| time >= cutoff);
+        // The first point may account for time before the window starts
+        let clip = |time: Instant, weight: f64| match cutoff {
+            Some(cutoff) => weight.min(time.saturating_duration_since(cutoff).as_secs_f64()),
+            None => weight,
+        };
+        if window <= self.raw_retention() {
+            return self
+                .raw
+                .iter()
+                .filter(|(time, _, _)| after(*time))
+                .map(|&(time, value, weight)| {
+                    let value = value.into();
+                    Point {
+                        time,
+                        min: value,
+                        avg: value,
+                        max: value,
+                        weight: clip(time, weight),
+                        count: 1,
+                    }
+                })
+                .collect();
+        }
+        let tier = self
+            .tiers
+            .iter()
+            .find(|tier| window <= tier.retention)
+            .unwrap_or(&self.tiers[self.tiers.len() - 1]);
+        tier.buckets
+            .iter()
+            .filter(|bucket| after(bucket.start))
+            .map(|bucket| {
+                let point = bucket.point();
+                Point {
+                    weight: clip(point.time + tier.resolution, point.weight),
+                    ..point
+                }
+            })
+            .collect()
+    }
+
+    // Time-weighted summary of the window; percentiles of rolled-up data use bucket averages
+    pub fn stats(&self, now: Instant, window: Duration) -> Stats {
+        let points = self.points(now, window);
+        if points.is_empty() {
+            return Stats::default();
+        }
+        let total_weight: f64 = points.iter().map(|p| p.weight).sum();
+        let mean = if total_weight > 0.0 {
+            points.iter().map(|p| p.avg * p.weight).sum::<f64>() / total_weight
+        } else {
+            points.iter().map(|p| p.avg).sum::<f64>() / points.len() as f64
+        };
+        Stats {
+            mean,
+            min: points.iter().map(|p| p.min).fold(f64::INFINITY, f64::min),
+            max: points
+                .iter()
+                .map(|p| p.max)
+                .fold(f64::NEG_INFINITY, f64::max),
+            p50: weighted_percentile(&points, 50.0),
+            p95: weighted_percentile(&points, 95.0),
+        }
+    }
+
+    // Mean, min and max since the first sample; percentiles only reach back as far as retention
+    pub fn session_stats(&self, now: Instant) -> Stats {
+        if self.session.count == 0 {
+            return Stats::default();
+        }
+        Stats {
+            mean: self.session.mean(),
+            min: self.session.min,
+            max: self.session.max,
+            ..self.stats(now, self.retention())
+        }
+    }
+}
+
+fn weighted_percentile(points: &[Point], p: f64) -> f64 {
+    if points.is_empty() {
+        return 0.0;
+    }
+    let mut values: Vec<(f64, f64)> = points.iter().map(|p| (p.avg, p.weight)).collect();
+    values.sort_by(|a, b| a.0.total_cmp(&b.0));
+    // Fall back to equal weights when no intervals are known yet
+    let total: f64 = values.iter().map(|&(_, w)| w).sum();
+    let (total, uniform) = if total > 0.0 {
+        (total, false)
+    } else {
+        (values.len() as f64, true)
+    };
+    let target = total * (p / 100.0).clamp(0.0, 1.0);
+    let mut seen = 0.0;
+    for &(value, weight) in &values {
+        seen += if uniform { 1.0 } else { weight };
+        if seen >= target {
+            return value;
+        }
+    }
+    values[values.len() - 1].0
+}
+
+fn retain_since<T>(
+    items: &mut VecDeque<T>,
+    time_of: impl Fn(&T) -> Instant,
+    now: Instant,
+    keep: Duration,
+) {
+    let Some(cutoff) = now.checked_sub(keep) else {
+        return;
+    };
+    while let Some(item) = items.front() {
+        if time_of(item) < cutoff {
+            items.pop_front();
+        } else {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Values pushed at the given offsets from a common start, each with the seconds it measured;
+    // also returns when the last one arrived
+    fn series(values: &[(f64, f64, f64)]) -> (TimeSeries<f64>, Instant) {
+        let start = Instant::now();
+        let mut series = TimeSeries::new();
+        let mut now = start;
+        for &(offset, value, weight) in values {
+            now = start + Duration::from_secs_f64(offset);
+            series.push_at(now, value, weight);
+        }
+        (series, now)
+    }
+
+    #[test]
+    fn summarises_the_window() {
+        let values: Vec<(f64, f64, f64)> = (1..=10).map(|i| (i as f64, i as f64, 1.0)).collect();
+        let (series, now) = series(&values);
+        let stats = series.stats(now, Duration::from_secs(60));
+        assert_eq!(stats.mean, 5.5);
+        assert_eq!((stats.min, stats.max), (1.0, 10.0));
+        assert_eq!(stats.p50, 5.0);
+        assert_eq!(stats.p95, 10.0);
+
+        let session = series.session_stats(now);
+        assert_eq!(session.mean, 5.5);
+        assert_eq!((session.min, session.max), (1.0, 10.0));
+        assert_eq!(series.last(), Some(10.0));
+    }
+
+    #[test]
+    fn weights_values_by_their_measured_interval() {
+        // A stalled sample arriving a second after the one before still covers the 9s it measured
+        let (series, now) = series(&[
+            (0.0, 5.0, 1.0),
+            (1.0, 5.0, 1.0),
+            (2.0, 5.0, 1.0),
+            (3.0, 20.0, 9.0),
+        ]);
+        let stats = series.stats(now, Duration::from_secs(60));
+        assert_eq!(stats.mean, 16.25);
+        assert_eq!(stats.p50, 20.0);
+    }
+
+    #[test]
+    fn clips_the_first_point_to_the_window() {
+        let (series, now) = series(&[(0.0, 5.0, 1.0), (1.0, 5.0, 1.0), (2.0, 20.0, 1.0)]);
+        let points = series.points(now, Duration::from_millis(1500));
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[0].weight, 0.5);
+        assert_eq!(series.stats(now, Duration::from_millis(1500)).mean, 15.0);
+    }
+
+    #[test]
+    fn rolls_up_older_data() {
+        let values: Vec<(f64, f64, f64)> = (0..1800)
+            .map(|i| (i as f64, (i % 10) as f64, 1.0))
+            .collect();
+        let (series, now) = series(&values);
+        // Raw samples only reach back 10 minutes
+        assert_eq!(series.points(now, Duration::from_secs(600)).len(), 601);
+
+        let points = series.points(now, Duration::from_secs(20 * 60));
+        assert_eq!(points.len(), 120);
+        for point in &points {
+            assert_eq!(point.avg, 4.5);
+            assert_eq!((point.min, point.max), (0.0, 9.0));
+            assert_eq!(point.weight, 10.0);
+        }
+        let stats = series.stats(now, Duration::from_secs(20 * 60));
+        assert_eq!((stats.mean, stats.min, stats.max), (4.5, 0.0, 9.0));
+        assert_eq!(series.raw_retention(), Duration::from_secs(600));
+        assert_eq!(series.retention(), MAX_RETENTION);
+    }
+
+    #[test]
+    fn summarises_nothing_as_zeros() {
+        let series = TimeSeries::<f64>::new();
+        let now = Instant::now();
+        let stats = series.stats(now, Duration::from_secs(60));
+        assert_eq!((stats.mean, stats.max, stats.p95), (0.0, 0.0, 0.0));
+        assert_eq!(series.session_stats(now).mean, 0.0);
+        assert_eq!(series.last(), None);
+    }
+}